@@ -1,6 +1,7 @@
 use indexmap::{IndexMap, IndexSet};
 use serde_derive::{Deserialize, Serialize};
 use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
 
 #[derive(Debug, Deserialize, Serialize)]
 #[serde(tag = "event", rename_all = "camelCase")]
@@ -10,6 +11,12 @@ pub enum FromInspector {
     SetFeatures {
         selected_parameters: BTreeMap<String, BTreeSet<String>>,
     },
+    SetOptions {
+        options: Options,
+    },
+    Recover {
+        recovery: Recovery,
+    },
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -19,6 +26,298 @@ pub enum ToInspector {
     SetFeatures {
         selected_parameters: IndexMap<String, IndexMap<String, bool>>,
     },
+    SetStatus {
+        status: PluginStatus,
+    },
+    SetOptions {
+        options: Options,
+    },
+    SetQuarantine {
+        quarantine: Option<Quarantine>,
+    },
+    SetCards {
+        cards: Vec<Card>,
+    },
+    SetCatalog {
+        catalog: Catalog,
+    },
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PluginStatus {
+    /// Whether changes made outside the plugin are being noticed.
+    pub is_watching: bool,
+    /// The last thing that went wrong, until something works again.
+    #[serde(default)]
+    pub last_error: Option<PluginError>,
+    /// The name of the default playback device, while it is not the
+    /// configured sound card and the plugin is paused.
+    #[serde(default)]
+    pub paused_for: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorKind {
+    DeviceNotFound,
+    Unsupported,
+    Driver,
+    ParameterRejected,
+    Timeout,
+    SettingsInvalid,
+    /// The plugin can no longer make sound card calls.
+    Stopped,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PluginError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+/// Stored settings that could not be loaded.
+///
+/// The plugin will not save until the user decides what to do with them.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Quarantine {
+    /// Why the settings could not be loaded.
+    pub error: String,
+    /// The stored settings as JSON.
+    pub settings: String,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Recovery {
+    /// Keep whatever parts of the stored settings can be read.
+    Salvage,
+    /// Replace the stored settings with what the plugin has now.
+    Discard,
+}
+
+/// A playback device that a key can be set to switch.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Card {
+    /// The endpoint ID.
+    pub id: String,
+    /// The name shown in Windows.
+    pub name: String,
+}
+
+/// Which way a Select Output key switches.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Direction {
+    /// Switch to whichever allowed output is not active.
+    Toggle,
+    /// Always switch to headphones.
+    Headphones,
+    /// Always switch to speakers.
+    Speakers,
+}
+
+/// Settings for one Select Output, Select Headphones, or Select Speakers
+/// key, stored by the Stream Deck software with the key.
+///
+/// The direction and the outputs to toggle between only matter for Select
+/// Output.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SelectOutputSettings {
+    /// The endpoint ID of the sound card to switch, or `None` for whichever
+    /// card the plugin is using.
+    pub card: Option<String>,
+    /// Whether toggling may switch to headphones.
+    pub headphones: bool,
+    /// Whether toggling may switch to speakers.
+    pub speakers: bool,
+    pub direction: Direction,
+    /// Whether to apply the volume saved with the profile.
+    pub apply_volume: bool,
+    /// Whether to apply the profile again when the output is already selected.
+    pub reapply: bool,
+    /// A template for a title showing the output, volume and parameters,
+    /// or empty to keep the title chosen in the Stream Deck software.
+    pub title: String,
+    /// Whether to draw the volume and badges on the key.
+    pub image: bool,
+}
+
+impl Default for SelectOutputSettings {
+    fn default() -> Self {
+        SelectOutputSettings {
+            card: None,
+            headphones: true,
+            speakers: true,
+            direction: Direction::Toggle,
+            apply_volume: true,
+            reapply: false,
+            title: String::new(),
+            image: false,
+        }
+    }
+}
+
+/// The kind of value a SoundCore parameter holds.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ParameterKind {
+    Bool,
+    I32,
+    U32,
+    Float,
+}
+
+/// What the sound card says a parameter accepts.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ParameterInfo {
+    pub kind: ParameterKind,
+    /// The smallest value the card accepts, if it says.
+    pub min: Option<f64>,
+    /// The largest value the card accepts, if it says.
+    pub max: Option<f64>,
+    /// The smallest change the card accepts, if it says.
+    pub step: Option<f64>,
+}
+
+impl ParameterInfo {
+    /// Both ends of the range, if the card says what they are.
+    pub fn range(&self) -> Option<(f64, f64)> {
+        match (self.min, self.max) {
+            (Some(min), Some(max)) if min <= max => Some((min, max)),
+            _ => None,
+        }
+    }
+
+    /// Move `value` into the range, leaving it alone where the card does not
+    /// say how far the parameter goes.
+    pub fn clamp(&self, value: f64) -> f64 {
+        let value = self.min.map_or(value, |min| value.max(min));
+        self.max.map_or(value, |max| value.min(max))
+    }
+
+    /// Whether the parameter can be set to `value`, which must be `true` or
+    /// `false` for a `Bool` parameter and a number of the right kind within
+    /// the range, as far as it is known, otherwise.
+    pub fn accepts(&self, value: &serde_json::Value) -> bool {
+        let number = match (self.kind, value) {
+            (ParameterKind::Bool, value) => return value.is_boolean(),
+            (ParameterKind::Float, value) => value.as_f64(),
+            (ParameterKind::I32, value) => value
+                .as_i64()
+                .filter(|n| i32::try_from(*n).is_ok())
+                .map(|n| n as f64),
+            (ParameterKind::U32, value) => value
+                .as_u64()
+                .filter(|n| u32::try_from(*n).is_ok())
+                .map(|n| n as f64),
+        };
+        number.is_some_and(|n| {
+            !self.min.is_some_and(|min| n < min) && !self.max.is_some_and(|max| n > max)
+        })
+    }
+}
+
+/// What every parameter of the sound card accepts, by feature.
+pub type Catalog = IndexMap<String, IndexMap<String, ParameterInfo>>;
+
+/// Settings for one Adjust Parameter key, stored by the Stream Deck software
+/// with the key.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct AdjustSettings {
+    pub feature: String,
+    pub parameter: String,
+    /// How much each press changes the value, negative to turn it down.
+    pub step: f64,
+}
+
+impl Default for AdjustSettings {
+    fn default() -> Self {
+        AdjustSettings {
+            feature: String::new(),
+            parameter: String::new(),
+            step: 1.0,
+        }
+    }
+}
+
+/// Settings for one Set Parameter key, stored by the Stream Deck software
+/// with the key.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SetParameterSettings {
+    pub feature: String,
+    pub parameter: String,
+    /// The value to set, or `None` until one has been chosen.
+    pub value: Option<serde_json::Value>,
+}
+
+/// One of the values a Cycle Parameter key steps through.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct CycleValue {
+    pub value: u32,
+    /// What the key's title shows while the parameter has this value.
+    pub label: String,
+}
+
+/// Settings for one Cycle Parameter key, stored by the Stream Deck software
+/// with the key.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CycleSettings {
+    pub feature: String,
+    pub parameter: String,
+    /// The values to step through, in order.
+    pub values: Vec<CycleValue>,
+}
+
+/// What a dial adjusts.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DialTarget {
+    Volume,
+    /// The `Float` parameter named in the settings.
+    Parameter,
+}
+
+/// What pressing a dial does.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DialPress {
+    /// Mute or unmute the sound card.
+    Mute,
+    /// Go back to the value stored with the dial.
+    Reset,
+}
+
+/// Settings for one dial, stored by the Stream Deck software with the dial.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DialSettings {
+    pub target: DialTarget,
+    pub feature: String,
+    pub parameter: String,
+    /// How far each tick moves the value, in percent of its range.
+    pub step: f32,
+    pub press: DialPress,
+    /// The value to go back to when pressed, with the volume in percent.
+    pub reset: Option<f32>,
+}
+
+impl Default for DialSettings {
+    fn default() -> Self {
+        DialSettings {
+            target: DialTarget::Volume,
+            feature: String::new(),
+            parameter: String::new(),
+            step: 2.0,
+            press: DialPress::Mute,
+            reset: None,
+        }
+    }
 }
 
 #[derive(Debug, Default, Deserialize, Serialize)]
@@ -36,13 +335,120 @@ pub struct SerdeProfiles {
     pub speakers: SerdeProfile,
 }
 
+/// Settings that control how the plugin behaves rather than what it remembers.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Options {
+    /// How long to wait after the last change before saving.
+    pub save_delay_ms: u64,
+    /// How long after a key press further presses of the same key are ignored.
+    pub press_debounce_ms: u64,
+    /// How many times to try talking to the sound card before giving up.
+    pub retry_attempts: u32,
+    /// How long to wait before the first retry.
+    pub retry_delay_ms: u64,
+    /// What to do when the sound card comes back after going missing.
+    pub on_card_return: ReturnPolicy,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReturnPolicy {
+    /// Apply the profile for the output that was active before the card went missing.
+    Reapply,
+    /// Keep whatever the card is set to and remember it as the active profile.
+    Recapture,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            save_delay_ms: 5000,
+            press_debounce_ms: 250,
+            retry_attempts: 3,
+            retry_delay_ms: 200,
+            on_card_return: ReturnPolicy::Reapply,
+        }
+    }
+}
+
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct SerdeCardSettings {
     #[serde(default)]
     pub selected_parameters: IndexMap<String, IndexSet<String>>,
     #[serde(default)]
     pub profiles: SerdeProfiles,
+    #[serde(default)]
+    pub options: Options,
+    /// The endpoint ID of the sound card the profiles were captured from.
+    #[serde(default)]
+    pub card: Option<String>,
 }
 
 #[derive(Debug, Default, Deserialize, Serialize)]
 pub struct Empty {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn info(kind: ParameterKind, min: Option<f64>, max: Option<f64>) -> ParameterInfo {
+        ParameterInfo {
+            kind,
+            min,
+            max,
+            step: None,
+        }
+    }
+
+    #[test]
+    fn accepts_numbers_within_the_range() {
+        let float = info(ParameterKind::Float, Some(-12.0), Some(12.0));
+        assert!(float.accepts(&json!(-12.0)));
+        assert!(float.accepts(&json!(12)));
+        assert!(float.accepts(&json!(0.5)));
+        assert!(!float.accepts(&json!(12.5)));
+        assert!(!float.accepts(&json!("1")));
+
+        let whole = info(ParameterKind::I32, Some(-5.0), Some(5.0));
+        assert!(whole.accepts(&json!(-5)));
+        assert!(!whole.accepts(&json!(6)));
+        assert!(!whole.accepts(&json!(1.5)));
+    }
+
+    #[test]
+    fn accepts_only_booleans_for_switches() {
+        let switch = info(ParameterKind::Bool, None, None);
+        assert!(switch.accepts(&json!(true)));
+        assert!(switch.accepts(&json!(false)));
+        assert!(!switch.accepts(&json!(1)));
+    }
+
+    #[test]
+    fn accepts_any_number_of_the_kind_without_a_range() {
+        let float = info(ParameterKind::Float, None, None);
+        assert!(float.accepts(&json!(1000.0)));
+
+        let unsigned = info(ParameterKind::U32, None, None);
+        assert!(unsigned.accepts(&json!(u32::max_value())));
+        assert!(!unsigned.accepts(&json!(-1)));
+        assert!(!unsigned.accepts(&json!(u64::from(u32::max_value()) + 1)));
+
+        let signed = info(ParameterKind::I32, Some(0.0), None);
+        assert!(signed.accepts(&json!(i32::max_value())));
+        assert!(!signed.accepts(&json!(-1)));
+        assert!(!signed.accepts(&json!(i64::from(i32::max_value()) + 1)));
+    }
+
+    #[test]
+    fn clamp_keeps_to_the_known_ends() {
+        assert_eq!(
+            info(ParameterKind::Float, Some(0.0), Some(1.0)).clamp(2.0),
+            1.0
+        );
+        assert_eq!(info(ParameterKind::Float, Some(0.0), None).clamp(-1.0), 0.0);
+        assert_eq!(info(ParameterKind::Float, Some(0.0), None).clamp(5.0), 5.0);
+        assert_eq!(info(ParameterKind::Float, None, None).clamp(-5.0), -5.0);
+    }
+}
@@ -0,0 +1,108 @@
+use super::catalog;
+use common::{AdjustSettings, Catalog, ParameterKind};
+use yew::prelude::*;
+
+const KINDS: &[ParameterKind] = &[ParameterKind::I32, ParameterKind::U32, ParameterKind::Float];
+
+#[derive(Clone, Default, PartialEq)]
+pub struct Properties {
+    pub settings: AdjustSettings,
+    pub catalog: Catalog,
+    pub onchange: Option<Callback<AdjustSettings>>,
+}
+
+/// The settings for a single Adjust Parameter key.
+pub struct AdjustPanel {
+    settings: AdjustSettings,
+    catalog: Catalog,
+    onchange: Option<Callback<AdjustSettings>>,
+}
+
+pub enum Message {
+    SetParameter(String),
+    SetStep(String),
+}
+
+impl Component for AdjustPanel {
+    type Message = Message;
+    type Properties = Properties;
+
+    fn create(properties: Self::Properties, _link: ComponentLink<Self>) -> Self {
+        Self {
+            settings: properties.settings,
+            catalog: properties.catalog,
+            onchange: properties.onchange,
+        }
+    }
+
+    fn update(&mut self, message: Self::Message) -> ShouldRender {
+        let mut settings = self.settings.clone();
+        match message {
+            Message::SetParameter(value) => match catalog::split(&value) {
+                Some((feature, parameter)) => {
+                    settings.feature = feature;
+                    settings.parameter = parameter;
+                }
+                None => return false,
+            },
+            Message::SetStep(value) => match value.trim().parse() {
+                Ok(step) => settings.step = step,
+                _ => return false,
+            },
+        }
+        catalog::emit(&self.onchange, &self.settings, settings);
+        false
+    }
+
+    fn change(&mut self, properties: Self::Properties) -> ShouldRender {
+        let changed = self.settings != properties.settings || self.catalog != properties.catalog;
+        self.settings = properties.settings;
+        self.catalog = properties.catalog;
+        self.onchange = properties.onchange;
+        changed
+    }
+}
+
+impl Renderable<AdjustPanel> for AdjustPanel {
+    fn view(&self) -> Html<Self> {
+        let settings = &self.settings;
+        let parameters =
+            catalog::parameters(&self.catalog, KINDS, &settings.feature, &settings.parameter);
+        let range = catalog::info(&self.catalog, &settings.feature, &settings.parameter)
+            .and_then(catalog::range)
+            .map(|range| format!("The sound card accepts values {}.", range))
+            .unwrap_or_default();
+        html! {
+            <details open=true,>
+                <summary>{ "This key" }</summary>
+                <div class="sdpi-item",>
+                    <div class="sdpi-item-label",>{ "Parameter" }</div>
+                    <select class="sdpi-item-value select",
+                        onchange=|e| Message::SetParameter(catalog::change_value(e)),>
+                        <option value="", disabled=true, selected=settings.feature.is_empty(),>
+                            { "Choose a parameter" }
+                        </option>
+                        { for parameters.into_iter().map(|(feature, parameter)| {
+                            let selected = feature == settings.feature && parameter == settings.parameter;
+                            html! {
+                                <option value=catalog::option_value(&feature, &parameter), selected=selected,>
+                                    { format!("{}: {}", feature, parameter) }
+                                </option>
+                            }
+                        }) }
+                    </select>
+                </div>
+                <div class="sdpi-item",>
+                    <div class="sdpi-item-label",>{ "Step" }</div>
+                    <input class="sdpi-item-value", type="number",
+                        value=settings.step.to_string(),
+                        onchange=|e| Message::SetStep(catalog::change_value(e)), />
+                </div>
+                <details class="message",>
+                    <summary>{ "Each press adds the step, or subtracts it if it is negative." }</summary>
+                    <p>{ range }</p>
+                </details>
+            </details>
+        }
+    }
+}
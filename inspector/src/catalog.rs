@@ -0,0 +1,79 @@
+//! Helpers for choosing a parameter from the sound card's catalog, and for
+//! the panels that do so.
+
+use common::{Catalog, ParameterInfo, ParameterKind};
+use yew::prelude::*;
+
+/// The new value of an input or select that has changed.
+pub fn change_value(data: ChangeData) -> String {
+    match data {
+        ChangeData::Select(select) => select.value().unwrap_or_default(),
+        ChangeData::Value(value) => value,
+        _ => String::new(),
+    }
+}
+
+/// Pass changed settings up to the panel's owner, unless nothing changed.
+pub fn emit<T: PartialEq>(onchange: &Option<Callback<T>>, current: &T, settings: T) {
+    if settings != *current {
+        if let Some(callback) = onchange {
+            callback.emit(settings);
+        }
+    }
+}
+
+/// The parameters of the given kinds, plus the one already chosen even if
+/// the card does not have it.
+pub fn parameters(
+    catalog: &Catalog,
+    kinds: &[ParameterKind],
+    feature: &str,
+    parameter: &str,
+) -> Vec<(String, String)> {
+    let mut parameters = catalog
+        .iter()
+        .flat_map(|(feature, parameters)| {
+            parameters
+                .iter()
+                .filter(|(_, info)| kinds.contains(&info.kind))
+                .map(move |(parameter, _)| (feature.to_owned(), parameter.to_owned()))
+        })
+        .collect::<Vec<_>>();
+    let current = (feature.to_owned(), parameter.to_owned());
+    if !feature.is_empty() && !parameters.contains(&current) {
+        parameters.push(current);
+    }
+    parameters
+}
+
+/// What the card accepts for a parameter, if it has it.
+pub fn info<'a>(catalog: &'a Catalog, feature: &str, parameter: &str) -> Option<&'a ParameterInfo> {
+    catalog
+        .get(feature)
+        .and_then(|parameters| parameters.get(parameter))
+}
+
+/// How far a parameter goes, such as `from 0 to 1`, or `None` if the card
+/// does not say.
+pub fn range(info: &ParameterInfo) -> Option<String> {
+    match (info.min, info.max) {
+        (Some(min), Some(max)) => Some(format!("from {} to {}", min, max)),
+        (Some(min), None) => Some(format!("from {} up", min)),
+        (None, Some(max)) => Some(format!("up to {}", max)),
+        (None, None) => None,
+    }
+}
+
+/// The value of an option for a parameter, as `Feature/Parameter`.
+pub fn option_value(feature: &str, parameter: &str) -> String {
+    format!("{}/{}", feature, parameter)
+}
+
+/// Split the value of an option back into a feature and a parameter.
+pub fn split(value: &str) -> Option<(String, String)> {
+    let mut parts = value.splitn(2, '/');
+    match (parts.next(), parts.next()) {
+        (Some(feature), Some(parameter)) => Some((feature.to_owned(), parameter.to_owned())),
+        _ => None,
+    }
+}
@@ -0,0 +1,178 @@
+use super::catalog;
+use common::{Catalog, CycleSettings, CycleValue, ParameterKind};
+use yew::prelude::*;
+
+#[derive(Clone, Default, PartialEq)]
+pub struct Properties {
+    pub settings: CycleSettings,
+    pub catalog: Catalog,
+    pub onchange: Option<Callback<CycleSettings>>,
+}
+
+/// The settings for a single Cycle Parameter key.
+pub struct CyclePanel {
+    settings: CycleSettings,
+    catalog: Catalog,
+    onchange: Option<Callback<CycleSettings>>,
+}
+
+pub enum Message {
+    SetParameter(String),
+    SetValue(usize, String),
+    SetLabel(usize, String),
+    Add,
+    Remove(usize),
+}
+
+impl CyclePanel {
+    /// Whether the card accepts `value` for the chosen parameter, or `true`
+    /// if the card's parameters are not known.
+    fn accepts(&self, value: u32) -> bool {
+        match catalog::info(
+            &self.catalog,
+            &self.settings.feature,
+            &self.settings.parameter,
+        ) {
+            Some(info) => info.accepts(&serde_json::Value::from(value)),
+            None => true,
+        }
+    }
+}
+
+impl Component for CyclePanel {
+    type Message = Message;
+    type Properties = Properties;
+
+    fn create(properties: Self::Properties, _link: ComponentLink<Self>) -> Self {
+        Self {
+            settings: properties.settings,
+            catalog: properties.catalog,
+            onchange: properties.onchange,
+        }
+    }
+
+    fn update(&mut self, message: Self::Message) -> ShouldRender {
+        let mut settings = self.settings.clone();
+        match message {
+            Message::SetParameter(value) => match catalog::split(&value) {
+                Some((feature, parameter)) => {
+                    // the values of some other parameter are unlikely to make sense
+                    if feature != settings.feature || parameter != settings.parameter {
+                        settings.values.clear();
+                    }
+                    settings.feature = feature;
+                    settings.parameter = parameter;
+                }
+                None => return false,
+            },
+            Message::SetValue(i, value) => match value.trim().parse() {
+                Ok(value) if i < settings.values.len() && self.accepts(value) => {
+                    settings.values[i].value = value;
+                }
+                _ => return false,
+            },
+            Message::SetLabel(i, label) => match settings.values.get_mut(i) {
+                Some(value) => value.label = label.trim().to_owned(),
+                None => return false,
+            },
+            Message::Add => {
+                let value = settings
+                    .values
+                    .iter()
+                    .map(|value| value.value.saturating_add(1))
+                    .max()
+                    .unwrap_or(0);
+                let value =
+                    match catalog::info(&self.catalog, &settings.feature, &settings.parameter) {
+                        Some(info) if !info.accepts(&serde_json::Value::from(value)) => {
+                            info.min.unwrap_or_default() as u32
+                        }
+                        _ => value,
+                    };
+                settings.values.push(CycleValue {
+                    value,
+                    label: String::new(),
+                });
+            }
+            Message::Remove(i) => {
+                if i < settings.values.len() {
+                    settings.values.remove(i);
+                }
+            }
+        }
+        catalog::emit(&self.onchange, &self.settings, settings);
+        false
+    }
+
+    fn change(&mut self, properties: Self::Properties) -> ShouldRender {
+        let changed = self.settings != properties.settings || self.catalog != properties.catalog;
+        self.settings = properties.settings;
+        self.catalog = properties.catalog;
+        self.onchange = properties.onchange;
+        changed
+    }
+}
+
+impl Renderable<CyclePanel> for CyclePanel {
+    fn view(&self) -> Html<Self> {
+        let settings = &self.settings;
+        let parameters = catalog::parameters(
+            &self.catalog,
+            &[ParameterKind::U32],
+            &settings.feature,
+            &settings.parameter,
+        );
+        let range = catalog::info(&self.catalog, &settings.feature, &settings.parameter)
+            .and_then(catalog::range)
+            .map(|range| format!("The sound card accepts values {}.", range))
+            .unwrap_or_default();
+        html! {
+            <details open=true,>
+                <summary>{ "This key" }</summary>
+                <div class="sdpi-item",>
+                    <div class="sdpi-item-label",>{ "Parameter" }</div>
+                    <select class="sdpi-item-value select",
+                        onchange=|e| Message::SetParameter(catalog::change_value(e)),>
+                        <option value="", disabled=true, selected=settings.feature.is_empty(),>
+                            { "Choose a parameter" }
+                        </option>
+                        { for parameters.into_iter().map(|(feature, parameter)| {
+                            let selected = feature == settings.feature && parameter == settings.parameter;
+                            html! {
+                                <option value=catalog::option_value(&feature, &parameter), selected=selected,>
+                                    { format!("{}: {}", feature, parameter) }
+                                </option>
+                            }
+                        }) }
+                    </select>
+                </div>
+                { for settings.values.iter().enumerate().map(|(i, value)| {
+                    let invalid = !self.accepts(value.value);
+                    html! {
+                        <div class="sdpi-item",>
+                            <div class="sdpi-item-label",>{ if invalid { "Not accepted" } else { "Value" } }</div>
+                            <input class="sdpi-item-value", type="number", min="0", step="1",
+                                value=value.value.to_string(),
+                                onchange=|e| Message::SetValue(i, catalog::change_value(e)), />
+                            <input class="sdpi-item-value", type="text", placeholder="Label",
+                                value=&value.label,
+                                onchange=|e| Message::SetLabel(i, catalog::change_value(e)), />
+                            <button class="sdpi-item-value", onclick=|_| Message::Remove(i),>
+                                { "Remove" }
+                            </button>
+                        </div>
+                    }
+                }) }
+                <div class="sdpi-item",>
+                    <button class="sdpi-item-value", onclick=|_| Message::Add,>
+                        { "Add a value" }
+                    </button>
+                </div>
+                <details class="message",>
+                    <summary>{ "Each press moves to the next value, and the title shows the label of the current one." }</summary>
+                    <p>{ range }</p>
+                </details>
+            </details>
+        }
+    }
+}
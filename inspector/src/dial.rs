@@ -0,0 +1,148 @@
+use super::catalog;
+use common::{Catalog, DialPress, DialSettings, DialTarget, ParameterKind};
+use yew::prelude::*;
+
+#[derive(Clone, Default, PartialEq)]
+pub struct Properties {
+    pub settings: DialSettings,
+    pub catalog: Catalog,
+    pub onchange: Option<Callback<DialSettings>>,
+}
+
+/// The settings for a single Stream Deck + dial.
+pub struct DialPanel {
+    settings: DialSettings,
+    catalog: Catalog,
+    onchange: Option<Callback<DialSettings>>,
+}
+
+pub enum Message {
+    SetTarget(String),
+    SetStep(String),
+    SetPress(String),
+    SetReset(String),
+}
+
+impl Component for DialPanel {
+    type Message = Message;
+    type Properties = Properties;
+
+    fn create(properties: Self::Properties, _link: ComponentLink<Self>) -> Self {
+        Self {
+            settings: properties.settings,
+            catalog: properties.catalog,
+            onchange: properties.onchange,
+        }
+    }
+
+    fn update(&mut self, message: Self::Message) -> ShouldRender {
+        let mut settings = self.settings.clone();
+        match message {
+            Message::SetTarget(value) => match catalog::split(&value) {
+                Some((feature, parameter)) => {
+                    settings.target = DialTarget::Parameter;
+                    settings.feature = feature;
+                    settings.parameter = parameter;
+                }
+                None => settings.target = DialTarget::Volume,
+            },
+            Message::SetStep(value) => match value.trim().parse() {
+                Ok(step) if step > 0.0 => settings.step = step,
+                _ => return false,
+            },
+            Message::SetPress(value) => {
+                settings.press = match value.as_str() {
+                    "mute" => DialPress::Mute,
+                    "reset" => DialPress::Reset,
+                    _ => return false,
+                };
+            }
+            Message::SetReset(value) => {
+                let value = value.trim();
+                if value.is_empty() {
+                    settings.reset = None;
+                } else if let Ok(reset) = value.parse() {
+                    settings.reset = Some(reset);
+                } else {
+                    return false;
+                }
+            }
+        }
+        catalog::emit(&self.onchange, &self.settings, settings);
+        false
+    }
+
+    fn change(&mut self, properties: Self::Properties) -> ShouldRender {
+        let changed = self.settings != properties.settings || self.catalog != properties.catalog;
+        self.settings = properties.settings;
+        self.catalog = properties.catalog;
+        self.onchange = properties.onchange;
+        changed
+    }
+}
+
+impl Renderable<DialPanel> for DialPanel {
+    fn view(&self) -> Html<Self> {
+        let settings = &self.settings;
+        let is_volume = settings.target == DialTarget::Volume;
+        let parameters = if is_volume {
+            catalog::parameters(&self.catalog, &[ParameterKind::Float], "", "")
+        } else {
+            catalog::parameters(
+                &self.catalog,
+                &[ParameterKind::Float],
+                &settings.feature,
+                &settings.parameter,
+            )
+        };
+        let reset = settings
+            .reset
+            .map(|reset| reset.to_string())
+            .unwrap_or_default();
+        html! {
+            <details open=true,>
+                <summary>{ "This dial" }</summary>
+                <div class="sdpi-item",>
+                    <div class="sdpi-item-label",>{ "Adjusts" }</div>
+                    <select class="sdpi-item-value select",
+                        onchange=|e| Message::SetTarget(catalog::change_value(e)),>
+                        <option value="", selected=is_volume,>{ "Volume" }</option>
+                        { for parameters.into_iter().map(|(feature, parameter)| {
+                            let selected = !is_volume
+                                && feature == settings.feature
+                                && parameter == settings.parameter;
+                            html! {
+                                <option value=catalog::option_value(&feature, &parameter), selected=selected,>
+                                    { format!("{}: {}", feature, parameter) }
+                                </option>
+                            }
+                        }) }
+                    </select>
+                </div>
+                <div class="sdpi-item",>
+                    <div class="sdpi-item-label",>{ "Step (% of range)" }</div>
+                    <input class="sdpi-item-value", type="number", min="0.1", step="0.5",
+                        value=settings.step.to_string(),
+                        onchange=|e| Message::SetStep(catalog::change_value(e)), />
+                </div>
+                <div class="sdpi-item",>
+                    <div class="sdpi-item-label",>{ "When pressed" }</div>
+                    <select class="sdpi-item-value select",
+                        onchange=|e| Message::SetPress(catalog::change_value(e)),>
+                        <option value="mute", selected=settings.press == DialPress::Mute,>
+                            { "Mute or unmute" }
+                        </option>
+                        <option value="reset", selected=settings.press == DialPress::Reset,>
+                            { "Reset" }
+                        </option>
+                    </select>
+                </div>
+                <div class="sdpi-item",>
+                    <div class="sdpi-item-label",>{ if is_volume { "Reset to (%)" } else { "Reset to" } }</div>
+                    <input class="sdpi-item-value", type="number", value=reset,
+                        onchange=|e| Message::SetReset(catalog::change_value(e)), />
+                </div>
+            </details>
+        }
+    }
+}
@@ -1,3 +1,5 @@
+#![recursion_limit = "512"]
+
 extern crate indexmap;
 extern crate serde;
 extern crate serde_derive;
@@ -6,14 +8,29 @@ extern crate stdweb;
 #[macro_use]
 extern crate yew;
 
+mod adjust;
+mod catalog;
+mod cycle;
+mod dial;
 mod feature;
 mod message;
+mod options;
 mod parameter;
+mod preview;
 mod registration;
+mod select_output;
+mod set_parameter;
 mod socket;
 
+use adjust::AdjustPanel;
+use cycle::CyclePanel;
+use dial::DialPanel;
 use feature::Feature;
 use indexmap::IndexMap;
+use options::OptionsPanel;
+use select_output::SelectOutputPanel;
+use serde::de::DeserializeOwned;
+use set_parameter::SetParameterPanel;
 use socket::{StreamDeckSocketService, StreamDeckSocketTask};
 use std::collections::BTreeSet;
 use stdweb::js_export;
@@ -21,8 +38,14 @@ use stdweb::web::{document, INode};
 use yew::prelude::*;
 use yew::services::websocket::WebSocketStatus;
 
-type Message = message::Message<common::SerdeCardSettings, common::Empty, common::ToInspector>;
-type RegistrationParams = registration::InspectorRegistrationParams<common::Empty>;
+const ACTION_SELECT_OUTPUT: &str = "io.github.mdonoughe.sbzdeck.selectoutput";
+const ACTION_DIAL: &str = "io.github.mdonoughe.sbzdeck.dial";
+const ACTION_ADJUST_PARAMETER: &str = "io.github.mdonoughe.sbzdeck.adjustparameter";
+const ACTION_SET_PARAMETER: &str = "io.github.mdonoughe.sbzdeck.setparameter";
+const ACTION_CYCLE_PARAMETER: &str = "io.github.mdonoughe.sbzdeck.cycleparameter";
+
+type Message = message::Message<common::SerdeCardSettings, serde_json::Value, common::ToInspector>;
+type RegistrationParams = registration::InspectorRegistrationParams<serde_json::Value>;
 
 #[js_export]
 fn connect_elgato_stream_deck_socket(
@@ -45,6 +68,16 @@ fn connect_elgato_stream_deck_socket(
         action_info,
     };
 
+    start(ComponentMessage::Connect(params));
+}
+
+/// Run the property inspector against canned data instead of the Stream Deck software.
+#[js_export]
+fn preview() {
+    start(ComponentMessage::Preview(preview::registration_params()));
+}
+
+fn start(message: ComponentMessage) {
     let body = document().body().unwrap();
     let target = document().create_element("div").unwrap();
     body.append_child(&target);
@@ -53,7 +86,7 @@ fn connect_elgato_stream_deck_socket(
     let mut scope = App::<Model>::new().mount(target);
     yew::run_loop();
 
-    scope.send_message(ComponentMessage::Connect(params));
+    scope.send_message(message);
 }
 
 #[derive(Clone, Default, Eq, PartialEq)]
@@ -66,19 +99,27 @@ pub struct Model {
     link: ComponentLink<Model>,
     socket: StreamDeckSocketService<
         common::SerdeCardSettings,
-        common::Empty,
+        serde_json::Value,
         common::ToInspector,
         common::FromInspector,
     >,
     task: Option<
-        StreamDeckSocketTask<common::SerdeCardSettings, common::Empty, common::FromInspector>,
+        StreamDeckSocketTask<common::SerdeCardSettings, serde_json::Value, common::FromInspector>,
     >,
     registration_params: Option<RegistrationParams>,
     selected_params: IndexMap<String, FeatureInfo>,
+    status: Option<common::PluginStatus>,
+    options: Option<common::Options>,
+    quarantine: Option<common::Quarantine>,
+    /// The settings of the key or dial this property inspector is for.
+    key_settings: serde_json::Value,
+    cards: Vec<common::Card>,
+    catalog: common::Catalog,
 }
 
 pub enum ComponentMessage {
     Connect(RegistrationParams),
+    Preview(RegistrationParams),
     Message(Message),
     Status(WebSocketStatus),
     SetParameter {
@@ -90,6 +131,14 @@ pub enum ComponentMessage {
         feature: String,
         is_expanded: bool,
     },
+    SetOptions(common::Options),
+    SetKeySettings(serde_json::Value),
+    Recover(common::Recovery),
+}
+
+/// Read the settings stored with the key, keeping the defaults for anything unreadable.
+fn key_settings<T: DeserializeOwned + Default>(settings: &serde_json::Value) -> T {
+    serde_json::from_value(settings.clone()).unwrap_or_default()
 }
 
 impl Component for Model {
@@ -103,6 +152,12 @@ impl Component for Model {
             task: None,
             selected_params: IndexMap::new(),
             registration_params: None,
+            status: None,
+            options: None,
+            quarantine: None,
+            key_settings: serde_json::Value::Null,
+            cards: Vec::new(),
+            catalog: common::Catalog::new(),
         }
     }
 
@@ -114,6 +169,18 @@ impl Component for Model {
                     self.link.send_back(ComponentMessage::Message),
                     self.link.send_back(ComponentMessage::Status),
                 ));
+                self.key_settings = message.action_info.payload.settings.clone();
+                self.registration_params = Some(message);
+                false
+            }
+            ComponentMessage::Preview(message) => {
+                let mut responder = preview::Responder::new();
+                self.task = Some(self.socket.connect_mock(
+                    move |text| responder.respond(text),
+                    self.link.send_back(ComponentMessage::Message),
+                    self.link.send_back(ComponentMessage::Status),
+                ));
+                self.key_settings = message.action_info.payload.settings.clone();
                 self.registration_params = Some(message);
                 false
             }
@@ -143,6 +210,21 @@ impl Component for Model {
                                 })
                                 .collect()
                         }
+                        common::ToInspector::SetStatus { status } => {
+                            self.status = Some(status);
+                        }
+                        common::ToInspector::SetOptions { options } => {
+                            self.options = Some(options);
+                        }
+                        common::ToInspector::SetQuarantine { quarantine } => {
+                            self.quarantine = quarantine;
+                        }
+                        common::ToInspector::SetCards { cards } => {
+                            self.cards = cards;
+                        }
+                        common::ToInspector::SetCatalog { catalog } => {
+                            self.catalog = catalog;
+                        }
                     },
                     _ => {}
                 }
@@ -220,6 +302,170 @@ impl Component for Model {
                 }
                 changed
             }
+            ComponentMessage::SetOptions(options) => {
+                let task = self.task.as_mut().unwrap();
+                let registration_params = self.registration_params.as_ref().unwrap();
+                task.send(&message::MessageOut::SendToPlugin {
+                    action: registration_params.action_info.action.to_string(),
+                    context: registration_params.property_inspector_uuid.to_string(),
+                    payload: common::FromInspector::SetOptions {
+                        options: options.clone(),
+                    },
+                });
+                self.options = Some(options);
+                true
+            }
+            ComponentMessage::SetKeySettings(settings) => {
+                let task = self.task.as_mut().unwrap();
+                let registration_params = self.registration_params.as_ref().unwrap();
+                task.send(&message::MessageOut::SetSettings {
+                    context: registration_params.property_inspector_uuid.to_string(),
+                    payload: settings.clone(),
+                });
+                self.key_settings = settings;
+                true
+            }
+            ComponentMessage::Recover(recovery) => {
+                let task = self.task.as_mut().unwrap();
+                let registration_params = self.registration_params.as_ref().unwrap();
+                task.send(&message::MessageOut::SendToPlugin {
+                    action: registration_params.action_info.action.to_string(),
+                    context: registration_params.property_inspector_uuid.to_string(),
+                    payload: common::FromInspector::Recover { recovery },
+                });
+                false
+            }
+        }
+    }
+}
+
+impl Model {
+    fn view_status(&self) -> Html<Self> {
+        if let Some(name) = self
+            .status
+            .as_ref()
+            .and_then(|status| status.paused_for.as_ref())
+        {
+            return html! {
+                <details class="message caution",>
+                    <summary>{ "Paused while another device is the default playback device." }</summary>
+                    <p>{ format!("{} is the default playback device. sbzdeck will resume once the Sound Blaster card is the default again.", name) }</p>
+                </details>
+            };
+        }
+        match self.status {
+            Some(ref status) if !status.is_watching => html! {
+                <div>
+                    <details class="message caution",>
+                        <summary>{ "Changes made outside of sbzdeck are not being noticed. Retrying…" }</summary>
+                    </details>
+                    { self.view_error() }
+                </div>
+            },
+            _ => self.view_error(),
+        }
+    }
+
+    fn view_error(&self) -> Html<Self> {
+        let error = match self
+            .status
+            .as_ref()
+            .and_then(|status| status.last_error.as_ref())
+        {
+            Some(error) => error,
+            None => return html! { <div></div> },
+        };
+        let advice = match error.kind {
+            common::ErrorKind::DeviceNotFound => {
+                "Make sure the Sound Blaster card is installed and is the default playback device."
+            }
+            common::ErrorKind::Unsupported => {
+                "This sound card does not let sbzdeck switch between headphones and speakers."
+            }
+            common::ErrorKind::Driver => {
+                "The sound card driver reported an error. Restarting the computer or reinstalling the driver may help."
+            }
+            common::ErrorKind::ParameterRejected => {
+                "The sound card refused a saved value. Try unchecking the parameter that changed most recently below."
+            }
+            common::ErrorKind::Timeout => {
+                "The sound card driver is not responding. sbzdeck will keep trying."
+            }
+            common::ErrorKind::SettingsInvalid => "The saved settings could not be read.",
+            common::ErrorKind::Stopped => {
+                "sbzdeck can no longer reach the sound card. Restarting the Stream Deck software will start it again."
+            }
+        };
+        html! {
+            <details class="message caution",>
+                <summary>{ advice }</summary>
+                <p>{ &error.message }</p>
+            </details>
+        }
+    }
+
+    fn view_quarantine(&self) -> Html<Self> {
+        match self.quarantine {
+            Some(ref quarantine) => html! {
+                <details class="message caution", open=true,>
+                    <summary>{ "The saved settings could not be read and will not be saved over until you choose what to do with them." }</summary>
+                    <p>{ &quarantine.error }</p>
+                    <textarea readonly=true, rows="8", value=&quarantine.settings,></textarea>
+                    <div class="sdpi-item",>
+                        <button class="sdpi-item-value",
+                            onclick=|_| ComponentMessage::Recover(common::Recovery::Salvage),>
+                            { "Recover what can be read" }
+                        </button>
+                        <button class="sdpi-item-value",
+                            onclick=|_| ComponentMessage::Recover(common::Recovery::Discard),>
+                            { "Discard saved settings" }
+                        </button>
+                    </div>
+                </details>
+            },
+            None => html! { <div></div> },
+        }
+    }
+
+    fn action(&self) -> &str {
+        self.registration_params
+            .as_ref()
+            .map(|params| params.action_info.action.as_str())
+            .unwrap_or_default()
+    }
+
+    fn view_key(&self) -> Html<Self> {
+        match self.action() {
+            ACTION_DIAL => html! {
+                <DialPanel: settings=key_settings::<common::DialSettings>(&self.key_settings), catalog=&self.catalog,
+                    onchange=|settings| ComponentMessage::SetKeySettings(serde_json::to_value(settings).unwrap()), />
+            },
+            ACTION_ADJUST_PARAMETER => html! {
+                <AdjustPanel: settings=key_settings::<common::AdjustSettings>(&self.key_settings), catalog=&self.catalog,
+                    onchange=|settings| ComponentMessage::SetKeySettings(serde_json::to_value(settings).unwrap()), />
+            },
+            ACTION_SET_PARAMETER => html! {
+                <SetParameterPanel: settings=key_settings::<common::SetParameterSettings>(&self.key_settings), catalog=&self.catalog,
+                    onchange=|settings| ComponentMessage::SetKeySettings(serde_json::to_value(settings).unwrap()), />
+            },
+            ACTION_CYCLE_PARAMETER => html! {
+                <CyclePanel: settings=key_settings::<common::CycleSettings>(&self.key_settings), catalog=&self.catalog,
+                    onchange=|settings| ComponentMessage::SetKeySettings(serde_json::to_value(settings).unwrap()), />
+            },
+            action => html! {
+                <SelectOutputPanel: settings=key_settings::<common::SelectOutputSettings>(&self.key_settings), cards=&self.cards,
+                    one_way=action != ACTION_SELECT_OUTPUT,
+                    onchange=|settings| ComponentMessage::SetKeySettings(serde_json::to_value(settings).unwrap()), />
+            },
+        }
+    }
+
+    fn view_options(&self) -> Html<Self> {
+        match self.options {
+            Some(ref options) => html! {
+                <OptionsPanel: options=options, onchange=|options| ComponentMessage::SetOptions(options), />
+            },
+            None => html! { <div></div> },
         }
     }
 }
@@ -228,6 +474,9 @@ impl Renderable<Model> for Model {
     fn view(&self) -> Html<Self> {
         html! {
             <div>
+                { self.view_status() }
+                { self.view_quarantine() }
+                { self.view_key() }
                 { for self.selected_params.iter().map(|(name, info)| {
                     let cb1_name = name.to_string();
                     let cb2_name = name.to_string();
@@ -246,6 +495,7 @@ impl Renderable<Model> for Model {
                             } }, />
                     }
                 }) }
+                { self.view_options() }
             </div>
         }
     }
@@ -0,0 +1,142 @@
+use super::catalog;
+use yew::prelude::*;
+
+#[derive(Clone, Default, PartialEq)]
+pub struct Properties {
+    pub options: common::Options,
+    pub onchange: Option<Callback<common::Options>>,
+}
+
+pub struct OptionsPanel {
+    options: common::Options,
+    onchange: Option<Callback<common::Options>>,
+}
+
+pub enum Message {
+    SetSaveDelay(String),
+    SetPressDebounce(String),
+    SetRetryAttempts(String),
+    SetRetryDelay(String),
+    SetOnCardReturn(String),
+}
+
+impl Component for OptionsPanel {
+    type Message = Message;
+    type Properties = Properties;
+
+    fn create(properties: Self::Properties, _link: ComponentLink<Self>) -> Self {
+        Self {
+            options: properties.options,
+            onchange: properties.onchange,
+        }
+    }
+
+    fn update(&mut self, message: Self::Message) -> ShouldRender {
+        match message {
+            Message::SetSaveDelay(value) => {
+                if let Ok(save_delay_ms) = value.trim().parse() {
+                    let options = common::Options {
+                        save_delay_ms,
+                        ..self.options.clone()
+                    };
+                    catalog::emit(&self.onchange, &self.options, options);
+                }
+            }
+            Message::SetPressDebounce(value) => {
+                if let Ok(press_debounce_ms) = value.trim().parse() {
+                    let options = common::Options {
+                        press_debounce_ms,
+                        ..self.options.clone()
+                    };
+                    catalog::emit(&self.onchange, &self.options, options);
+                }
+            }
+            Message::SetRetryAttempts(value) => {
+                if let Ok(retry_attempts) = value.trim().parse() {
+                    let options = common::Options {
+                        retry_attempts,
+                        ..self.options.clone()
+                    };
+                    catalog::emit(&self.onchange, &self.options, options);
+                }
+            }
+            Message::SetRetryDelay(value) => {
+                if let Ok(retry_delay_ms) = value.trim().parse() {
+                    let options = common::Options {
+                        retry_delay_ms,
+                        ..self.options.clone()
+                    };
+                    catalog::emit(&self.onchange, &self.options, options);
+                }
+            }
+            Message::SetOnCardReturn(value) => {
+                let on_card_return = match value.as_str() {
+                    "reapply" => common::ReturnPolicy::Reapply,
+                    "recapture" => common::ReturnPolicy::Recapture,
+                    _ => return false,
+                };
+                let options = common::Options {
+                    on_card_return,
+                    ..self.options.clone()
+                };
+                catalog::emit(&self.onchange, &self.options, options);
+            }
+        }
+        false
+    }
+
+    fn change(&mut self, properties: Self::Properties) -> ShouldRender {
+        let changed = self.options != properties.options;
+        self.options = properties.options;
+        self.onchange = properties.onchange;
+        changed
+    }
+}
+
+impl Renderable<OptionsPanel> for OptionsPanel {
+    fn view(&self) -> Html<Self> {
+        html! {
+            <details>
+                <summary>{ "Options" }</summary>
+                <div class="sdpi-item",>
+                    <div class="sdpi-item-label",>{ "Save delay (ms)" }</div>
+                    <input class="sdpi-item-value", type="number", min="0", step="100",
+                        value=self.options.save_delay_ms.to_string(),
+                        onchange=|e| Message::SetSaveDelay(catalog::change_value(e)), />
+                </div>
+                <div class="sdpi-item",>
+                    <div class="sdpi-item-label",>{ "Ignore repeat presses (ms)" }</div>
+                    <input class="sdpi-item-value", type="number", min="0", step="50",
+                        value=self.options.press_debounce_ms.to_string(),
+                        onchange=|e| Message::SetPressDebounce(catalog::change_value(e)), />
+                </div>
+                <div class="sdpi-item",>
+                    <div class="sdpi-item-label",>{ "Attempts per call" }</div>
+                    <input class="sdpi-item-value", type="number", min="1", step="1",
+                        value=self.options.retry_attempts.to_string(),
+                        onchange=|e| Message::SetRetryAttempts(catalog::change_value(e)), />
+                </div>
+                <div class="sdpi-item",>
+                    <div class="sdpi-item-label",>{ "Retry delay (ms)" }</div>
+                    <input class="sdpi-item-value", type="number", min="0", step="50",
+                        value=self.options.retry_delay_ms.to_string(),
+                        onchange=|e| Message::SetRetryDelay(catalog::change_value(e)), />
+                </div>
+                <div class="sdpi-item",>
+                    <div class="sdpi-item-label",>{ "When the card returns" }</div>
+                    <select class="sdpi-item-value select",
+                        onchange=|e| Message::SetOnCardReturn(catalog::change_value(e)),>
+                        <option value="reapply",
+                            selected=self.options.on_card_return == common::ReturnPolicy::Reapply,>
+                            { "Apply the last profile" }
+                        </option>
+                        <option value="recapture",
+                            selected=self.options.on_card_return == common::ReturnPolicy::Recapture,>
+                            { "Keep its current settings" }
+                        </option>
+                    </select>
+                </div>
+            </details>
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Canned data for running the property inspector in a browser without the
+//! Stream Deck software.
+
+use super::RegistrationParams;
+use indexmap::IndexMap;
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+
+const INFO: &str = r#"{
+    "application": { "language": "en", "platform": "windows", "version": "4.6.4" },
+    "devicePixelRatio": 1,
+    "devices": [
+        { "id": "PREVIEW-DEVICE", "size": { "columns": 5, "rows": 3 }, "type": 0 }
+    ]
+}"#;
+
+const ACTION_INFO: &str = r#"{
+    "action": "io.github.mdonoughe.sbzdeck.selectoutput",
+    "context": "PREVIEW-CONTEXT",
+    "device": "PREVIEW-DEVICE",
+    "payload": {
+        "settings": {},
+        "coordinates": { "column": 0, "row": 0 }
+    }
+}"#;
+
+const FEATURES: &[(&str, &[&str])] = &[
+    ("Device Control", &["SelectOutput"]),
+    ("SBX Pro Studio", &["SBX Pro Studio"]),
+    ("Surround", &["Surround", "Surround Level"]),
+    ("Crystalizer", &["Crystalizer", "Crystalizer Level"]),
+    ("Bass", &["Bass", "Bass Level", "Crossover Frequency"]),
+    (
+        "Smart Volume",
+        &[
+            "Smart Volume",
+            "Smart Volume Level",
+            "Smart Volume Special Mode",
+        ],
+    ),
+    ("Dialog Plus", &["Dialog Plus", "Dialog Plus Level"]),
+    (
+        "EQ",
+        &[
+            "EQ", "Pre-Amp", "31Hz", "62Hz", "125Hz", "250Hz", "500Hz", "1kHz", "2kHz", "4kHz",
+            "8kHz", "16kHz",
+        ],
+    ),
+];
+
+pub fn registration_params() -> RegistrationParams {
+    RegistrationParams {
+        url: "preview".to_string(),
+        property_inspector_uuid: "PREVIEW-INSPECTOR".to_string(),
+        register_event: "registerPropertyInspector".to_string(),
+        info: serde_json::from_str(INFO).unwrap(),
+        action_info: serde_json::from_str(ACTION_INFO).unwrap(),
+    }
+}
+
+/// Answers messages sent by the property inspector the way the plugin would.
+pub struct Responder {
+    selected_parameters: BTreeMap<String, BTreeSet<String>>,
+    options: common::Options,
+}
+
+impl Responder {
+    pub fn new() -> Self {
+        Self {
+            selected_parameters: vec![(
+                "SBX Pro Studio".to_string(),
+                vec!["SBX Pro Studio".to_string()].into_iter().collect(),
+            )]
+            .into_iter()
+            .collect(),
+            options: common::Options::default(),
+        }
+    }
+
+    pub fn respond(&mut self, text: &str) -> Vec<String> {
+        let message: serde_json::Value = serde_json::from_str(text).unwrap();
+        if message["event"] != "sendToPlugin" {
+            return Vec::new();
+        }
+        let action = &message["action"];
+        let context = &message["context"];
+        match serde_json::from_value(message["payload"].clone()).unwrap() {
+            common::FromInspector::GetFeatures => {
+                let features = common::ToInspector::SetFeatures {
+                    selected_parameters: self.features(),
+                };
+                let status = common::ToInspector::SetStatus {
+                    status: common::PluginStatus {
+                        is_watching: true,
+                        last_error: None,
+                        paused_for: None,
+                    },
+                };
+                let options = common::ToInspector::SetOptions {
+                    options: self.options.clone(),
+                };
+                let quarantine = common::ToInspector::SetQuarantine { quarantine: None };
+                let cards = common::ToInspector::SetCards {
+                    cards: vec![common::Card {
+                        id: "PREVIEW-CARD".to_string(),
+                        name: "Speakers (Sound Blaster Z)".to_string(),
+                    }],
+                };
+                let catalog = common::ToInspector::SetCatalog { catalog: catalog() };
+                vec![features, status, options, quarantine, cards, catalog]
+                    .into_iter()
+                    .map(|payload| {
+                        json!({
+                            "event": "sendToPropertyInspector",
+                            "action": action,
+                            "context": context,
+                            "payload": payload,
+                        })
+                        .to_string()
+                    })
+                    .collect()
+            }
+            common::FromInspector::SetFeatures {
+                selected_parameters,
+            } => {
+                self.selected_parameters = selected_parameters;
+                Vec::new()
+            }
+            common::FromInspector::SetOptions { options } => {
+                self.options = options;
+                Vec::new()
+            }
+            common::FromInspector::Recover { .. } => Vec::new(),
+        }
+    }
+
+    fn features(&self) -> IndexMap<String, IndexMap<String, bool>> {
+        FEATURES
+            .iter()
+            .map(|(feature, parameters)| {
+                let selection = self.selected_parameters.get(*feature);
+                (
+                    feature.to_string(),
+                    parameters
+                        .iter()
+                        .map(|parameter| {
+                            let selected = selection
+                                .map(|s| s.contains(*parameter))
+                                .unwrap_or_default();
+                            (parameter.to_string(), selected)
+                        })
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Ranges made up to look like a Sound Blaster Z.
+fn catalog() -> common::Catalog {
+    FEATURES
+        .iter()
+        .map(|(feature, parameters)| {
+            (
+                feature.to_string(),
+                parameters
+                    .iter()
+                    .map(|parameter| {
+                        let (kind, min, max, step) =
+                            if parameter.ends_with("Hz") || *parameter == "Pre-Amp" {
+                                (common::ParameterKind::Float, -12.0, 12.0, 0.0)
+                            } else if parameter.ends_with("Level") {
+                                (common::ParameterKind::Float, 0.0, 1.0, 0.0)
+                            } else if *parameter == "Crossover Frequency" {
+                                (common::ParameterKind::U32, 50.0, 250.0, 10.0)
+                            } else if *parameter == "SelectOutput"
+                                || *parameter == "Smart Volume Special Mode"
+                            {
+                                (common::ParameterKind::U32, 0.0, 2.0, 1.0)
+                            } else {
+                                (common::ParameterKind::Bool, 0.0, 1.0, 1.0)
+                            };
+                        (
+                            parameter.to_string(),
+                            common::ParameterInfo {
+                                kind,
+                                // like the card, switches have no range
+                                min: Some(min).filter(|_| kind != common::ParameterKind::Bool),
+                                max: Some(max).filter(|_| kind != common::ParameterKind::Bool),
+                                step: Some(step).filter(|step| *step > 0.0),
+                            },
+                        )
+                    })
+                    .collect(),
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,186 @@
+use super::catalog;
+use common::{Card, Direction, SelectOutputSettings};
+use stdweb::traits::*;
+use yew::prelude::*;
+
+#[derive(Clone, Default, PartialEq)]
+pub struct Properties {
+    pub settings: SelectOutputSettings,
+    pub cards: Vec<Card>,
+    /// Whether the key always switches to the same output.
+    pub one_way: bool,
+    pub onchange: Option<Callback<SelectOutputSettings>>,
+}
+
+/// The settings for a single Select Output, Select Headphones, or Select Speakers key.
+pub struct SelectOutputPanel {
+    settings: SelectOutputSettings,
+    cards: Vec<Card>,
+    one_way: bool,
+    onchange: Option<Callback<SelectOutputSettings>>,
+}
+
+pub enum Message {
+    SetCard(String),
+    SetDirection(String),
+    ToggleHeadphones,
+    ToggleSpeakers,
+    ToggleApplyVolume,
+    ToggleReapply,
+    SetTitle(String),
+    ToggleImage,
+}
+
+impl Component for SelectOutputPanel {
+    type Message = Message;
+    type Properties = Properties;
+
+    fn create(properties: Self::Properties, _link: ComponentLink<Self>) -> Self {
+        Self {
+            settings: properties.settings,
+            cards: properties.cards,
+            one_way: properties.one_way,
+            onchange: properties.onchange,
+        }
+    }
+
+    fn update(&mut self, message: Self::Message) -> ShouldRender {
+        let mut settings = self.settings.clone();
+        match message {
+            Message::SetCard(value) => {
+                settings.card = if value.is_empty() { None } else { Some(value) };
+            }
+            Message::SetDirection(value) => {
+                settings.direction = match value.as_str() {
+                    "toggle" => Direction::Toggle,
+                    "headphones" => Direction::Headphones,
+                    "speakers" => Direction::Speakers,
+                    _ => return false,
+                };
+            }
+            Message::ToggleHeadphones => settings.headphones = !settings.headphones,
+            Message::ToggleSpeakers => settings.speakers = !settings.speakers,
+            Message::ToggleApplyVolume => settings.apply_volume = !settings.apply_volume,
+            Message::ToggleReapply => settings.reapply = !settings.reapply,
+            Message::SetTitle(value) => settings.title = value,
+            Message::ToggleImage => settings.image = !settings.image,
+        }
+        catalog::emit(&self.onchange, &self.settings, settings);
+        false
+    }
+
+    fn change(&mut self, properties: Self::Properties) -> ShouldRender {
+        let changed = self.settings != properties.settings
+            || self.cards != properties.cards
+            || self.one_way != properties.one_way;
+        self.settings = properties.settings;
+        self.cards = properties.cards;
+        self.one_way = properties.one_way;
+        self.onchange = properties.onchange;
+        changed
+    }
+}
+
+impl SelectOutputPanel {
+    fn view_direction(&self) -> Html<Self> {
+        if self.one_way {
+            return html! { <div></div> };
+        }
+        let direction = self.settings.direction;
+        html! {
+            <div>
+                <div class="sdpi-item",>
+                    <div class="sdpi-item-label",>{ "When pressed" }</div>
+                    <select class="sdpi-item-value select",
+                        onchange=|e| Message::SetDirection(catalog::change_value(e)),>
+                        <option value="toggle", selected=direction == Direction::Toggle,>
+                            { "Toggle" }
+                        </option>
+                        <option value="headphones", selected=direction == Direction::Headphones,>
+                            { "Switch to headphones" }
+                        </option>
+                        <option value="speakers", selected=direction == Direction::Speakers,>
+                            { "Switch to speakers" }
+                        </option>
+                    </select>
+                </div>
+                // capitalize `type` because otherwise yew eats it
+                <div Type="checkbox", class="sdpi-item",>
+                    <div class="sdpi-item-label",>{ "Toggle between" }</div>
+                    <div class="sdpi-item-value min100",>
+                        <div class="sdpi-item-child", onclick=|e| { e.prevent_default(); Message::ToggleHeadphones },>
+                            <input id="toggle-headphones", type="checkbox", checked=self.settings.headphones,/>
+                            <label for="toggle-headphones", class="sdpi-item-label",><span></span>{ "Headphones" }</label>
+                        </div>
+                        <div class="sdpi-item-child", onclick=|e| { e.prevent_default(); Message::ToggleSpeakers },>
+                            <input id="toggle-speakers", type="checkbox", checked=self.settings.speakers,/>
+                            <label for="toggle-speakers", class="sdpi-item-label",><span></span>{ "Speakers" }</label>
+                        </div>
+                    </div>
+                </div>
+            </div>
+        }
+    }
+}
+
+impl Renderable<SelectOutputPanel> for SelectOutputPanel {
+    fn view(&self) -> Html<Self> {
+        let card = self.settings.card.clone().unwrap_or_default();
+        html! {
+            <details open=true,>
+                <summary>{ "This key" }</summary>
+                <div class="sdpi-item",>
+                    <div class="sdpi-item-label",>{ "Sound card" }</div>
+                    <select class="sdpi-item-value select",
+                        onchange=|e| Message::SetCard(catalog::change_value(e)),>
+                        <option value="", selected=card.is_empty(),>
+                            { "The card sbzdeck is using" }
+                        </option>
+                        { for self.cards.iter().map(|c| html! {
+                            <option value=&c.id, selected=c.id == card,>{ &c.name }</option>
+                        }) }
+                    </select>
+                </div>
+                { self.view_direction() }
+                // capitalize `type` because otherwise yew eats it
+                <div Type="checkbox", class="sdpi-item",>
+                    <div class="sdpi-item-label",>{ "Volume" }</div>
+                    <div class="sdpi-item-value min100",>
+                        <div class="sdpi-item-child", onclick=|e| { e.prevent_default(); Message::ToggleApplyVolume },>
+                            <input id="apply-volume", type="checkbox", checked=self.settings.apply_volume,/>
+                            <label for="apply-volume", class="sdpi-item-label",><span></span>{ "Apply saved volume" }</label>
+                        </div>
+                    </div>
+                </div>
+                <div Type="checkbox", class="sdpi-item",>
+                    <div class="sdpi-item-label",>{ "Already selected" }</div>
+                    <div class="sdpi-item-value min100",>
+                        <div class="sdpi-item-child", onclick=|e| { e.prevent_default(); Message::ToggleReapply },>
+                            <input id="reapply", type="checkbox", checked=self.settings.reapply,/>
+                            <label for="reapply", class="sdpi-item-label",><span></span>{ "Apply the profile again" }</label>
+                        </div>
+                    </div>
+                </div>
+                <div class="sdpi-item",>
+                    <div class="sdpi-item-label",>{ "Live title" }</div>
+                    <input class="sdpi-item-value", type="text", placeholder="{out} {volume}%",
+                        value=&self.settings.title,
+                        onchange=|e| Message::SetTitle(catalog::change_value(e)), />
+                </div>
+                <details class="message",>
+                    <summary>{ "Title placeholders" }</summary>
+                    <p>{ "{output} or {out} for the output, {volume} for the volume in percent, {n} for a new line, and {Feature/Parameter}, such as {SBX Pro Studio/SBX Pro Studio}, for any parameter. The title typed in the Stream Deck software takes precedence." }</p>
+                </details>
+                <div Type="checkbox", class="sdpi-item",>
+                    <div class="sdpi-item-label",>{ "Image" }</div>
+                    <div class="sdpi-item-value min100",>
+                        <div class="sdpi-item-child", onclick=|e| { e.prevent_default(); Message::ToggleImage },>
+                            <input id="image", type="checkbox", checked=self.settings.image,/>
+                            <label for="image", class="sdpi-item-label",><span></span>{ "Show volume, SBX and mute" }</label>
+                        </div>
+                    </div>
+                </div>
+            </details>
+        }
+    }
+}
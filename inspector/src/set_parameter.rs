@@ -0,0 +1,224 @@
+use super::catalog;
+use common::{Catalog, ParameterInfo, ParameterKind, SetParameterSettings};
+use yew::prelude::*;
+
+const KINDS: &[ParameterKind] = &[
+    ParameterKind::Bool,
+    ParameterKind::I32,
+    ParameterKind::U32,
+    ParameterKind::Float,
+];
+
+#[derive(Clone, Default, PartialEq)]
+pub struct Properties {
+    pub settings: SetParameterSettings,
+    pub catalog: Catalog,
+    pub onchange: Option<Callback<SetParameterSettings>>,
+}
+
+/// The settings for a single Set Parameter key.
+pub struct SetParameterPanel {
+    settings: SetParameterSettings,
+    catalog: Catalog,
+    onchange: Option<Callback<SetParameterSettings>>,
+    /// Why the value that was typed was not used.
+    rejected: Option<String>,
+}
+
+pub enum Message {
+    SetParameter(String),
+    SetValue(String),
+}
+
+/// Parse what was typed as the kind of value the parameter holds.
+fn parse(kind: ParameterKind, value: &str) -> Option<serde_json::Value> {
+    let value = value.trim();
+    match kind {
+        ParameterKind::Bool => value.parse::<bool>().ok().map(serde_json::Value::from),
+        ParameterKind::I32 | ParameterKind::U32 => {
+            value.parse::<i64>().ok().map(serde_json::Value::from)
+        }
+        ParameterKind::Float => value
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(serde_json::Value::Number),
+    }
+}
+
+fn describe(info: &ParameterInfo) -> String {
+    match info.kind {
+        ParameterKind::Bool => "On or off".to_owned(),
+        ParameterKind::I32 | ParameterKind::U32 => match catalog::range(info) {
+            Some(range) => format!("A whole number {}", range),
+            None => "A whole number".to_owned(),
+        },
+        ParameterKind::Float => match catalog::range(info) {
+            Some(range) => format!("A number {}", range),
+            None => "A number".to_owned(),
+        },
+    }
+}
+
+impl SetParameterPanel {
+    fn info(&self) -> Option<&ParameterInfo> {
+        catalog::info(
+            &self.catalog,
+            &self.settings.feature,
+            &self.settings.parameter,
+        )
+    }
+
+    fn view_value(&self, info: Option<&ParameterInfo>) -> Html<Self> {
+        let value = self.settings.value.as_ref();
+        match info {
+            Some(info) if info.kind == ParameterKind::Bool => html! {
+                <select class="sdpi-item-value select",
+                    onchange=|e| Message::SetValue(catalog::change_value(e)),>
+                    <option value="", disabled=true, selected=value.is_none(),>{ "Choose" }</option>
+                    <option value="true", selected=value.and_then(|v| v.as_bool()) == Some(true),>
+                        { "On" }
+                    </option>
+                    <option value="false", selected=value.and_then(|v| v.as_bool()) == Some(false),>
+                        { "Off" }
+                    </option>
+                </select>
+            },
+            Some(info) => {
+                let step = match info.kind {
+                    ParameterKind::Float => match info.step {
+                        Some(step) if step > 0.0 => step.to_string(),
+                        _ => "any".to_owned(),
+                    },
+                    _ => "1".to_owned(),
+                };
+                html! {
+                    <input class="sdpi-item-value", type="number",
+                        min=info.min.map(|min| min.to_string()).unwrap_or_default(),
+                        max=info.max.map(|max| max.to_string()).unwrap_or_default(),
+                        step=step,
+                        value=value.map(|value| value.to_string()).unwrap_or_default(),
+                        onchange=|e| Message::SetValue(catalog::change_value(e)), />
+                }
+            }
+            None => html! {
+                <input class="sdpi-item-value", type="text", disabled=true,
+                    value=value.map(|value| value.to_string()).unwrap_or_default(), />
+            },
+        }
+    }
+}
+
+impl Component for SetParameterPanel {
+    type Message = Message;
+    type Properties = Properties;
+
+    fn create(properties: Self::Properties, _link: ComponentLink<Self>) -> Self {
+        Self {
+            settings: properties.settings,
+            catalog: properties.catalog,
+            onchange: properties.onchange,
+            rejected: None,
+        }
+    }
+
+    fn update(&mut self, message: Self::Message) -> ShouldRender {
+        let mut settings = self.settings.clone();
+        match message {
+            Message::SetParameter(value) => match catalog::split(&value) {
+                Some((feature, parameter)) => {
+                    let info = catalog::info(&self.catalog, &feature, &parameter);
+                    // a value for some other parameter is unlikely to make sense
+                    if !settings
+                        .value
+                        .as_ref()
+                        .is_some_and(|value| info.is_some_and(|info| info.accepts(value)))
+                    {
+                        settings.value = None;
+                    }
+                    settings.feature = feature;
+                    settings.parameter = parameter;
+                }
+                None => return false,
+            },
+            Message::SetValue(value) => {
+                let info = match self.info() {
+                    Some(info) => info,
+                    None => return false,
+                };
+                match parse(info.kind, &value) {
+                    Some(ref value) if info.accepts(value) => {
+                        settings.value = Some(value.clone());
+                    }
+                    _ => {
+                        self.rejected = Some(format!("\"{}\" is not accepted.", value.trim()));
+                        return true;
+                    }
+                }
+            }
+        }
+        self.rejected = None;
+        catalog::emit(&self.onchange, &self.settings, settings);
+        true
+    }
+
+    fn change(&mut self, properties: Self::Properties) -> ShouldRender {
+        let changed = self.settings != properties.settings || self.catalog != properties.catalog;
+        self.settings = properties.settings;
+        self.catalog = properties.catalog;
+        self.onchange = properties.onchange;
+        changed
+    }
+}
+
+impl Renderable<SetParameterPanel> for SetParameterPanel {
+    fn view(&self) -> Html<Self> {
+        let settings = &self.settings;
+        let parameters =
+            catalog::parameters(&self.catalog, KINDS, &settings.feature, &settings.parameter);
+        let info = self.info();
+        let hint = match (info, &settings.value) {
+            _ if settings.feature.is_empty() => String::new(),
+            (None, _) if self.catalog.is_empty() => String::new(),
+            (None, _) => "The sound card does not have this parameter.".to_owned(),
+            (Some(info), Some(value)) if !info.accepts(value) => {
+                format!(
+                    "The sound card does not accept {}. {}.",
+                    value,
+                    describe(info)
+                )
+            }
+            (Some(info), _) => describe(info),
+        };
+        html! {
+            <details open=true,>
+                <summary>{ "This key" }</summary>
+                <div class="sdpi-item",>
+                    <div class="sdpi-item-label",>{ "Parameter" }</div>
+                    <select class="sdpi-item-value select",
+                        onchange=|e| Message::SetParameter(catalog::change_value(e)),>
+                        <option value="", disabled=true, selected=settings.feature.is_empty(),>
+                            { "Choose a parameter" }
+                        </option>
+                        { for parameters.into_iter().map(|(feature, parameter)| {
+                            let selected = feature == settings.feature && parameter == settings.parameter;
+                            html! {
+                                <option value=catalog::option_value(&feature, &parameter), selected=selected,>
+                                    { format!("{}: {}", feature, parameter) }
+                                </option>
+                            }
+                        }) }
+                    </select>
+                </div>
+                <div class="sdpi-item",>
+                    <div class="sdpi-item-label",>{ "Value" }</div>
+                    { self.view_value(info) }
+                </div>
+                <details class="message",>
+                    <summary>{ self.rejected.as_ref().map(String::as_str).unwrap_or("Each press sets the parameter to this value.") }</summary>
+                    <p>{ hint }</p>
+                </details>
+            </details>
+        }
+    }
+}
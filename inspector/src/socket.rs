@@ -3,17 +3,25 @@ use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_derive::Serialize;
 use std::marker::PhantomData;
+use stdweb::web::set_timeout;
 use yew::callback::Callback;
 use yew::format::{Binary, Text};
 use yew::services::websocket::{WebSocketService, WebSocketStatus, WebSocketTask};
+use yew::services::ConsoleService;
 
 pub struct StreamDeckSocketService<G, S, MI, MO> {
     inner: WebSocketService,
     _phantom: PhantomData<(G, S, MI, MO)>,
 }
 
+enum Transport {
+    WebSocket(WebSocketTask),
+    /// Messages are handed to a function instead of the Stream Deck software.
+    Mock(Box<dyn FnMut(String)>),
+}
+
 pub struct StreamDeckSocketTask<G, S, MO> {
-    inner: WebSocketTask,
+    inner: Transport,
     _phantom: PhantomData<(G, S, MO)>,
 }
 
@@ -25,12 +33,19 @@ where
 {
     pub fn send(&mut self, data: &MessageOut<G, S, MO>) {
         let message = serde_json::to_string(data).unwrap();
-        self.inner.send(Ok(message));
+        self.send_text(message);
     }
 
     pub fn register(&mut self, event: &str, uuid: &str) {
         let registration = serde_json::to_string(&Registration { event, uuid }).unwrap();
-        self.inner.send(Ok(registration));
+        self.send_text(registration);
+    }
+
+    fn send_text(&mut self, text: String) {
+        match self.inner {
+            Transport::WebSocket(ref mut task) => task.send(Ok(text)),
+            Transport::Mock(ref mut send) => send(text),
+        }
     }
 }
 
@@ -88,7 +103,40 @@ where
             Callback::from(move |status| notification.emit(status)),
         );
         StreamDeckSocketTask {
-            inner: task,
+            inner: Transport::WebSocket(task),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Create a task which is not connected to the Stream Deck software.
+    ///
+    /// Outgoing messages are written to the browser console and passed to
+    /// `respond`. Any messages it returns are delivered as if they had been
+    /// received from the Stream Deck software.
+    pub fn connect_mock<F>(
+        &mut self,
+        mut respond: F,
+        callback: Callback<Message<G, S, MI>>,
+        notification: Callback<WebSocketStatus>,
+    ) -> StreamDeckSocketTask<G, S, MO>
+    where
+        F: 'static + FnMut(&str) -> Vec<String>,
+    {
+        let mut console = ConsoleService::new();
+        let receive = Callback::from(move |text: String| {
+            let text: Text = Ok(text);
+            callback.emit(WsMessage::<G, S, MI>::from(text).message)
+        });
+        // deliver everything asynchronously like a real socket would
+        set_timeout(move || notification.emit(WebSocketStatus::Opened), 0);
+        StreamDeckSocketTask {
+            inner: Transport::Mock(Box::new(move |text| {
+                console.log(&format!("sent {}", text));
+                for response in respond(&text) {
+                    let receive = receive.clone();
+                    set_timeout(move || receive.emit(response), 0);
+                }
+            })),
             _phantom: PhantomData,
         }
     }
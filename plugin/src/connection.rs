@@ -0,0 +1,320 @@
+use crate::types::*;
+use futures::channel::{mpsc, oneshot};
+use futures::future::{self, Either};
+use futures::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use slog::{crit, debug, info, warn, Logger};
+use std::cmp;
+use std::collections::VecDeque;
+use std::time::Duration;
+use streamdeck_rs::registration::RegistrationParams;
+use streamdeck_rs::{Message, MessageOut};
+use tokio_tungstenite::tungstenite;
+
+pub type InMessage = Message<serde_json::Value, serde_json::Value, FromInspector>;
+
+/// A message from a Stream Deck + dial.
+///
+/// streamdeck-rs predates dials, so these are decoded separately.
+///
+/// [Official Documentation](https://docs.elgato.com/sdk/plugins/events-received)
+#[derive(Debug, Deserialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+pub enum DialMessage {
+    /// A dial has been turned.
+    DialRotate {
+        /// The instance of the action.
+        context: String,
+        payload: DialRotatePayload,
+    },
+    /// A dial has been pressed.
+    DialDown {
+        /// The instance of the action.
+        context: String,
+    },
+    /// A dial has been released.
+    DialUp {
+        /// The instance of the action.
+        context: String,
+    },
+    /// The touch strip has been tapped above a dial.
+    TouchTap {
+        /// The instance of the action.
+        context: String,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DialRotatePayload {
+    /// How far the dial was turned, negative for counterclockwise.
+    pub ticks: i32,
+}
+
+/// A message for a Stream Deck + dial.
+///
+/// [Official Documentation](https://docs.elgato.com/sdk/plugins/events-sent)
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+pub enum DialMessageOut {
+    /// Change what the touch strip shows above a dial, using the names of
+    /// the items in its layout.
+    SetFeedback {
+        context: String,
+        payload: serde_json::Value,
+    },
+}
+
+/// A message to be sent to the Stream Deck software.
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum OutMessage {
+    Sdk(MessageOut<serde_json::Value, serde_json::Value, ToInspector>),
+    Dial(DialMessageOut),
+}
+
+/// How many outgoing messages are kept while disconnected before the oldest are dropped.
+const MAX_BACKLOG: usize = 256;
+const MIN_RECONNECT_DELAY: Duration = Duration::from_millis(500);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Connect to the Stream Deck software and register the plugin.
+///
+/// This does not use the socket from streamdeck-rs, so that events it does
+/// not know about can still be decoded.
+async fn connect(
+    params: &RegistrationParams,
+) -> Result<
+    impl Stream<Item = Result<tungstenite::Message, tungstenite::Error>>
+        + Sink<tungstenite::Message, Error = tungstenite::Error>,
+    tungstenite::Error,
+> {
+    let url = format!("ws://localhost:{}", params.port);
+    let (mut socket, _) = tokio_tungstenite::connect_async(url.as_str()).await?;
+    let registration = serde_json::json!({
+        "event": params.event,
+        "uuid": params.uuid,
+    });
+    socket
+        .send(tungstenite::Message::Text(registration.to_string()))
+        .await?;
+    Ok(socket)
+}
+
+fn encode(message: &OutMessage) -> tungstenite::Message {
+    tungstenite::Message::Text(
+        serde_json::to_string(message).expect("failed to serialize outgoing message"),
+    )
+}
+
+/// Decode a message from the Stream Deck software, which may be about a dial.
+///
+/// streamdeck-rs decodes events it does not know as `Message::Unknown`, so
+/// dial events have to be tried first.
+fn decode(text: &str) -> Result<Command, serde_json::Error> {
+    serde_json::from_str(text)
+        .map(Command::Dial)
+        .or_else(|_| serde_json::from_str(text).map(Command::Message))
+}
+
+fn buffer(logger: &Logger, backlog: &mut VecDeque<OutMessage>, message: OutMessage) {
+    backlog.push_back(message);
+    if backlog.len() > MAX_BACKLOG {
+        warn!(logger, "dropping message queued while disconnected");
+        backlog.pop_front();
+    }
+}
+
+/// How a connection came to an end.
+enum Ending {
+    /// The plugin is stopping and everything queued has been sent.
+    Finished,
+    /// The connection was lost and should be made again.
+    Lost,
+}
+
+/// Keep the plugin connected to the Stream Deck software.
+///
+/// Messages from `outgoing` are sent to the Stream Deck software, or held
+/// until the connection is reestablished. Everything received is forwarded to
+/// `commands`.
+///
+/// If the Stream Deck software closes the connection, `closed` is notified
+/// and the socket is kept open so that last messages can still be written.
+/// Once `stop` fires, whatever has already been queued is sent and this
+/// returns.
+pub async fn run(
+    logger: Logger,
+    params: &RegistrationParams,
+    mut outgoing: mpsc::UnboundedReceiver<OutMessage>,
+    commands: mpsc::UnboundedSender<Command>,
+    closed: oneshot::Sender<()>,
+    mut stop: oneshot::Receiver<()>,
+) {
+    let mut closed = Some(closed);
+    let mut stopping = false;
+    let mut backlog = VecDeque::new();
+    let mut delay = MIN_RECONNECT_DELAY;
+    loop {
+        match connect(params).await {
+            Ok(socket) => {
+                info!(logger, "connected to Stream Deck");
+                delay = MIN_RECONNECT_DELAY;
+                let (mut sink, mut stream) = socket.split();
+
+                let sending = async {
+                    // settings may have changed while disconnected
+                    let get_settings = OutMessage::Sdk(MessageOut::GetGlobalSettings {
+                        context: params.uuid.to_string(),
+                    });
+                    if sink.send(encode(&get_settings)).await.is_err() {
+                        return Ending::Lost;
+                    }
+                    // messages leave the backlog only once they are sent, so
+                    // nothing is lost if the connection drops mid-send
+                    loop {
+                        while let Some(message) = backlog.front() {
+                            if sink.send(encode(message)).await.is_err() {
+                                return Ending::Lost;
+                            }
+                            backlog.pop_front();
+                        }
+                        if stopping {
+                            let _ = sink.close().await;
+                            return Ending::Finished;
+                        }
+                        match future::select(outgoing.next(), &mut stop).await {
+                            Either::Left((Some(message), _)) => backlog.push_back(message),
+                            Either::Left((None, _)) | Either::Right(_) => {
+                                // send what was queued before stopping, but nothing after
+                                while let Ok(Some(message)) = outgoing.try_next() {
+                                    backlog.push_back(message);
+                                }
+                                stopping = true;
+                            }
+                        }
+                    }
+                };
+
+                // whether the Stream Deck software closed the connection
+                let receiving = async {
+                    let _ = commands.unbounded_send(Command::Connected);
+                    while let Some(message) = stream.next().await {
+                        match message {
+                            Ok(tungstenite::Message::Text(text)) => match decode(&text) {
+                                // nobody is listening once the plugin is stopping
+                                Ok(command) => {
+                                    let _ = commands.unbounded_send(command);
+                                }
+                                // the Stream Deck software has events the plugin has no use for
+                                Err(error) => debug!(logger, "ignoring {}: {:?}", text, error),
+                            },
+                            Ok(tungstenite::Message::Close(_)) => return true,
+                            // pings are answered by tungstenite
+                            Ok(_) => {}
+                            Err(error) => {
+                                crit!(logger, "receive failed {:?}", error);
+                                return false;
+                            }
+                        }
+                    }
+                    false
+                };
+
+                let ending = match future::select(Box::pin(sending), Box::pin(receiving)).await {
+                    Either::Left((ending, _)) => ending,
+                    Either::Right((true, sending)) => {
+                        // the Stream Deck software is done with the plugin
+                        info!(logger, "Stream Deck closed the connection");
+                        if let Some(closed) = closed.take() {
+                            let _ = closed.send(());
+                        }
+                        sending.await;
+                        return;
+                    }
+                    Either::Right((false, _)) => Ending::Lost,
+                };
+                match ending {
+                    Ending::Finished => return,
+                    // there is no point in reconnecting just to stop
+                    Ending::Lost if stopping => return,
+                    Ending::Lost => warn!(logger, "disconnected from Stream Deck"),
+                }
+            }
+            Err(error) => {
+                warn!(logger, "connection failed: {:?}", error);
+                // keep accepting messages so the rest of the plugin does not stall
+                let deadline = tokio::time::Instant::now() + delay;
+                loop {
+                    match tokio::time::timeout_at(
+                        deadline,
+                        future::select(outgoing.next(), &mut stop),
+                    )
+                    .await
+                    {
+                        Ok(Either::Left((Some(message), _))) => {
+                            buffer(&logger, &mut backlog, message)
+                        }
+                        // stopping, with nowhere to send anything
+                        Ok(_) => return,
+                        Err(_) => break,
+                    }
+                }
+                delay = cmp::min(delay * 2, MAX_RECONNECT_DELAY);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_dial_rotate() {
+        let text = r#"{
+            "action": "io.github.mdonoughe.sbzdeck.dial",
+            "context": "dial context",
+            "device": "device id",
+            "event": "dialRotate",
+            "payload": {
+                "controller": "Encoder",
+                "coordinates": {"column": 0, "row": 0},
+                "pressed": false,
+                "settings": {},
+                "ticks": -2
+            }
+        }"#;
+        match decode(text) {
+            Ok(Command::Dial(DialMessage::DialRotate { context, payload })) => {
+                assert_eq!(context, "dial context");
+                assert_eq!(payload.ticks, -2);
+            }
+            other => panic!("decoded as {:?}", other),
+        }
+    }
+
+    #[test]
+    fn decode_dial_down() {
+        let text = r#"{
+            "action": "io.github.mdonoughe.sbzdeck.dial",
+            "context": "dial context",
+            "device": "device id",
+            "event": "dialDown",
+            "payload": {
+                "controller": "Encoder",
+                "coordinates": {"column": 0, "row": 0},
+                "settings": {}
+            }
+        }"#;
+        assert!(matches!(
+            decode(text),
+            Ok(Command::Dial(DialMessage::DialDown { .. }))
+        ));
+    }
+
+    #[test]
+    fn decode_other_events_with_streamdeck_rs() {
+        let text = r#"{"event": "systemDidWakeUp"}"#;
+        assert!(matches!(decode(text), Ok(Command::Message(_))));
+    }
+}
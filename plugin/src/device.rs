@@ -0,0 +1,236 @@
+use crate::error::Error;
+use crate::mirror::Mirror;
+use crate::sb::{self, ChangeEvent, Endpoint};
+use crate::types::*;
+use futures::channel::{mpsc, oneshot};
+use futures::executor;
+use futures::prelude::*;
+use indexmap::{IndexMap, IndexSet};
+use slog::{debug, warn, Logger};
+use std::ffi::OsStr;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// How long to wait for the sound card to be read.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long to wait for a profile to be applied, which touches many parameters.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How persistently to retry calls that fail with errors that tend to go away.
+#[derive(Clone, Copy, Debug)]
+pub struct Retry {
+    /// How many times to try, including the first.
+    pub attempts: u32,
+    /// How long to wait before the first retry. Doubled after each retry.
+    pub delay: Duration,
+}
+
+impl Retry {
+    /// Try only once.
+    pub fn once() -> Self {
+        Retry {
+            attempts: 1,
+            delay: Duration::default(),
+        }
+    }
+}
+
+impl From<&Options> for Retry {
+    fn from(options: &Options) -> Self {
+        Retry {
+            attempts: options.retry_attempts.max(1),
+            delay: Duration::from_millis(options.retry_delay_ms),
+        }
+    }
+}
+
+struct Job {
+    name: &'static str,
+    /// Runs the call unless the caller has already given up on it.
+    run: Box<dyn FnOnce(&Logger) + Send>,
+}
+
+/// A handle for talking to the sound card.
+///
+/// Driver calls block, sometimes for a long time, so they are made one at a
+/// time on a dedicated thread instead of on the runtime. Each call has a
+/// timeout, and calls whose callers have stopped waiting are skipped.
+#[derive(Clone)]
+pub struct Device {
+    jobs: mpsc::UnboundedSender<Job>,
+    logger: Logger,
+}
+
+impl Device {
+    pub fn spawn(logger: &Logger) -> Self {
+        let (jobs, mut job_rx) = mpsc::unbounded::<Job>();
+        let thread_logger = logger.clone();
+        thread::Builder::new()
+            .name("device thread".into())
+            .spawn(move || {
+                while let Some(job) = executor::block_on(job_rx.next()) {
+                    debug!(thread_logger, "calling {}", job.name);
+                    (job.run)(&thread_logger);
+                }
+            })
+            .expect("failed to start device thread");
+        Self {
+            jobs,
+            logger: logger.clone(),
+        }
+    }
+
+    async fn call<T, F>(&self, name: &'static str, timeout: Duration, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Logger) -> Result<T, Box<dyn std::error::Error>> + Send + 'static,
+    {
+        let (reply, result) = oneshot::channel();
+        let run = move |logger: &Logger| {
+            if reply.is_canceled() {
+                debug!(logger, "skipping {} because nobody is waiting for it", name);
+                return;
+            }
+            let _ = reply.send(f(logger).map_err(Error::from_driver));
+        };
+        self.jobs
+            .unbounded_send(Job {
+                name,
+                run: Box::new(run),
+            })
+            .map_err(|_| Error::Stopped)?;
+        match tokio::time::timeout(timeout, result).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(Error::Stopped),
+            Err(_) => {
+                warn!(self.logger, "{} did not finish within {:?}", name, timeout);
+                Err(Error::TimedOut(name))
+            }
+        }
+    }
+
+    async fn call_with_retry<T, F>(
+        &self,
+        name: &'static str,
+        timeout: Duration,
+        retry: Retry,
+        f: F,
+    ) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: Fn(&Logger) -> Result<T, Box<dyn std::error::Error>> + Send + Sync + 'static,
+    {
+        let f = Arc::new(f);
+        let mut delay = retry.delay;
+        let mut attempt = 1;
+        loop {
+            let f = f.clone();
+            match self.call(name, timeout, move |logger| f(logger)).await {
+                Err(error) if error.is_retryable() && attempt < retry.attempts => {
+                    warn!(
+                        self.logger,
+                        "{} failed on attempt {} of {}: {}; retrying in {:?}",
+                        name,
+                        attempt,
+                        retry.attempts,
+                        error,
+                        delay
+                    );
+                    tokio::time::delay_for(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Read everything `card`, or the default playback device if there is
+    /// none, is set to.
+    pub async fn dump(&self, card: Option<String>, retry: Retry) -> Result<Mirror, Error> {
+        self.call_with_retry("dump", READ_TIMEOUT, retry, move |logger| {
+            let configuration = sbz_switch::dump(logger, card.as_ref().map(OsStr::new))?;
+            // the dump leaves out whether the card is muted
+            let muted = match sb::is_muted(logger, card.as_ref().map(String::as_str)) {
+                Ok(muted) => Some(muted),
+                Err(error) => {
+                    debug!(
+                        logger,
+                        "could not read whether the card is muted: {}", error
+                    );
+                    None
+                }
+            };
+            Ok(Mirror::new(configuration, muted))
+        })
+        .await
+    }
+
+    /// Find out which playback device is the default.
+    pub async fn default_endpoint_id(&self) -> Result<String, Error> {
+        self.call("default_endpoint_id", READ_TIMEOUT, |logger| {
+            sb::default_endpoint_id(logger)
+        })
+        .await
+    }
+
+    /// Look up the name of a playback device.
+    pub async fn endpoint(&self, id: String) -> Result<Endpoint, Error> {
+        self.call("endpoint", READ_TIMEOUT, move |logger| {
+            sb::endpoint(logger, &id)
+        })
+        .await
+    }
+
+    /// List the playback devices.
+    pub async fn endpoints(&self) -> Result<Vec<Endpoint>, Error> {
+        self.call("endpoints", READ_TIMEOUT, |logger| sb::endpoints(logger))
+            .await
+    }
+
+    /// Find out what every parameter of `card`, or the default playback
+    /// device if there is none, accepts.
+    pub async fn catalog(&self, card: Option<String>, retry: Retry) -> Result<Catalog, Error> {
+        self.call_with_retry("catalog", READ_TIMEOUT, retry, move |logger| {
+            sb::catalog(logger, card.as_ref().map(String::as_str))
+        })
+        .await
+    }
+
+    /// Make one change, such as to the volume or a single parameter, to
+    /// `card`, or the default playback device if there is none.
+    pub async fn apply_change(
+        &self,
+        card: Option<String>,
+        change: ChangeEvent,
+        retry: Retry,
+    ) -> Result<(), Error> {
+        self.call_with_retry("apply_change", WRITE_TIMEOUT, retry, move |logger| {
+            sb::apply_change(logger, card.as_ref().map(String::as_str), &change)
+        })
+        .await
+    }
+
+    /// Switch `card`, or the default playback device if there is none, to
+    /// `output` and apply the selected parameters from `profile`.
+    pub async fn apply_profile(
+        &self,
+        card: Option<String>,
+        output: Output,
+        profile: Profile,
+        selected_parameters: IndexMap<String, IndexSet<String>>,
+        retry: Retry,
+    ) -> Result<(), Error> {
+        self.call_with_retry("apply_profile", WRITE_TIMEOUT, retry, move |logger| {
+            sb::apply_profile(
+                logger,
+                card.as_ref().map(String::as_str),
+                output,
+                &profile,
+                &selected_parameters,
+            )
+        })
+        .await
+    }
+}
@@ -0,0 +1,133 @@
+use common::ErrorKind;
+use sbz_switch::soundcore::SoundCoreError;
+use sbz_switch::Win32Error;
+use std::fmt;
+
+/// `HRESULT_FROM_WIN32(ERROR_NOT_FOUND)`, returned when there is no such endpoint.
+const E_NOTFOUND: i32 = 0x8007_0490_u32 as i32;
+/// `AUDCLNT_E_DEVICE_INVALIDATED`, returned when the endpoint has been removed.
+const AUDCLNT_E_DEVICE_INVALIDATED: i32 = 0x8889_0004_u32 as i32;
+/// `E_INVALIDARG`, returned when the driver does not accept a value.
+const E_INVALIDARG: i32 = 0x8007_0057_u32 as i32;
+
+/// Something that went wrong while working with the sound card or its settings.
+#[derive(Debug)]
+pub enum Error {
+    /// There is no sound card to talk to.
+    DeviceNotFound,
+    /// The sound card does not have the Creative SoundCore interface, or
+    /// cannot switch outputs.
+    Unsupported,
+    /// The driver failed with an `HRESULT`.
+    Win32(i32),
+    /// sbz-switch failed in some other way.
+    Driver(String),
+    /// The driver refused one of the values it was given.
+    ParameterRejected(String),
+    /// The driver did not answer in time. The call may still finish later.
+    TimedOut(&'static str),
+    /// The stored settings could not be read.
+    SettingsInvalid(String),
+    /// The device thread is no longer running.
+    Stopped,
+}
+
+impl Error {
+    /// Work out what went wrong from an error reported by sbz-switch.
+    pub fn from_driver(error: Box<dyn std::error::Error>) -> Self {
+        match error.downcast_ref::<SoundCoreError>() {
+            Some(SoundCoreError::Win32(error)) => return Error::from_hresult(error.code),
+            Some(SoundCoreError::NotSupported) => return Error::Unsupported,
+            _ => {}
+        }
+        if let Some(error) = error.downcast_ref::<Win32Error>() {
+            return Error::from_hresult(error.code);
+        }
+        Error::Driver(error.to_string())
+    }
+
+    fn from_hresult(code: i32) -> Self {
+        match code {
+            E_NOTFOUND | AUDCLNT_E_DEVICE_INVALIDATED => Error::DeviceNotFound,
+            E_INVALIDARG => Error::ParameterRejected(format!("error {:#010x}", code)),
+            code => Error::Win32(code),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::DeviceNotFound => ErrorKind::DeviceNotFound,
+            Error::Unsupported => ErrorKind::Unsupported,
+            Error::Win32(_) | Error::Driver(_) => ErrorKind::Driver,
+            Error::ParameterRejected(_) => ErrorKind::ParameterRejected,
+            Error::TimedOut(_) => ErrorKind::Timeout,
+            Error::SettingsInvalid(_) => ErrorKind::SettingsInvalid,
+            Error::Stopped => ErrorKind::Stopped,
+        }
+    }
+
+    /// Whether trying again shortly might work.
+    ///
+    /// Drivers return errors for a moment after resuming or resetting the
+    /// device. A call that timed out is not retried, because the retry would
+    /// only wait behind the stuck call.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Error::DeviceNotFound | Error::Win32(_) => true,
+            Error::Unsupported
+            | Error::Driver(_)
+            | Error::ParameterRejected(_)
+            | Error::TimedOut(_)
+            | Error::SettingsInvalid(_)
+            | Error::Stopped => false,
+        }
+    }
+
+    /// Whether whatever is known about the sound card should be read again.
+    pub fn invalidates_device(&self) -> bool {
+        match self {
+            Error::DeviceNotFound
+            | Error::Win32(_)
+            | Error::Driver(_)
+            | Error::ParameterRejected(_) => true,
+            Error::Unsupported
+            | Error::TimedOut(_)
+            | Error::SettingsInvalid(_)
+            | Error::Stopped => false,
+        }
+    }
+
+    /// A short title for the key that hit the error.
+    pub fn key_title(&self) -> &'static str {
+        match self {
+            Error::DeviceNotFound => "No card",
+            Error::Unsupported => "Not\nsupported",
+            Error::Win32(_) | Error::Driver(_) => "Driver\nerror",
+            Error::ParameterRejected(_) => "Rejected",
+            Error::TimedOut(_) => "Not\nresponding",
+            Error::SettingsInvalid(_) => "Settings\nerror",
+            Error::Stopped => "Stopped",
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::DeviceNotFound => f.write_str("the sound card could not be found"),
+            Error::Unsupported => f.write_str("the sound card does not support switching outputs"),
+            Error::Win32(code) => write!(f, "the driver failed with error {:#010x}", code),
+            Error::Driver(message) => write!(f, "the driver failed: {}", message),
+            Error::ParameterRejected(message) => {
+                write!(f, "the sound card rejected a value: {}", message)
+            }
+            Error::TimedOut(call) => write!(f, "the driver did not finish {} in time", call),
+            Error::SettingsInvalid(message) => {
+                write!(f, "the saved settings could not be read: {}", message)
+            }
+            Error::Stopped => f.write_str("the device thread has stopped"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
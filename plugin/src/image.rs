@@ -0,0 +1,173 @@
+use crate::types::*;
+use std::collections::HashMap;
+
+const HEADPHONES: &[u8] = include_bytes!("../../actionHeadphones.png");
+const HEADPHONES_2X: &[u8] = include_bytes!("../../actionHeadphones@2x.png");
+const SPEAKERS: &[u8] = include_bytes!("../../actionSpeaker.png");
+const SPEAKERS_2X: &[u8] = include_bytes!("../../actionSpeaker@2x.png");
+
+/// How many steps the volume bar has. Fewer steps mean fewer images to send
+/// while the volume is being dragged.
+const VOLUME_STEPS: f32 = 20.0;
+
+/// Everything a rendered key image shows.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Look {
+    pub output: Output,
+    /// The volume in steps of the volume bar, if known.
+    pub volume: Option<u8>,
+    pub muted: bool,
+    pub sbx: bool,
+}
+
+impl Look {
+    pub fn new(output: Output, volume: Option<f32>, muted: bool, sbx: bool) -> Self {
+        Look {
+            output,
+            volume: volume.map(|volume| (volume.max(0.0).min(1.0) * VOLUME_STEPS).round() as u8),
+            muted,
+            sbx,
+        }
+    }
+}
+
+/// How many pixels across key images are on a deck with `columns` by `rows`
+/// keys, shown on a display with `pixel_ratio`.
+pub fn key_size(columns: u8, rows: u8, pixel_ratio: u8) -> u32 {
+    let size = match (columns, rows) {
+        // Stream Deck XL
+        (8, 4) => 96,
+        // Stream Deck +
+        (4, 2) => 120,
+        // Stream Deck Mini
+        (3, 2) => 80,
+        _ => 72,
+    };
+    size * u32::from(pixel_ratio.max(1))
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let bits = (u32::from(bytes[0]) << 16) | (u32::from(bytes[1]) << 8) | u32::from(bytes[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[((bits >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn badge(x: u32, fill: &str, text: &str) -> String {
+    format!(
+        concat!(
+            r##"<rect x="{}" y="8" width="44" height="22" rx="4" fill="{}"/>"##,
+            r##"<text x="{}" y="25" font-family="Arial, sans-serif" font-size="16" "##,
+            r##"font-weight="bold" text-anchor="middle" fill="#f0f0f0">{}</text>"##,
+        ),
+        x,
+        fill,
+        x + 22,
+        text
+    )
+}
+
+/// Draw a key image as an SVG data URL, `size` pixels square.
+///
+/// The existing artwork for the output is drawn with a volume bar along the
+/// bottom and badges for SBX and muting along the top.
+pub fn render(look: &Look, size: u32) -> String {
+    let artwork = match (look.output, size > 72) {
+        (Output::Headphones, false) => HEADPHONES,
+        (Output::Headphones, true) => HEADPHONES_2X,
+        (Output::Speakers, false) => SPEAKERS,
+        (Output::Speakers, true) => SPEAKERS_2X,
+    };
+    let mut svg = format!(
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" "#,
+            r#"width="{0}" height="{0}" viewBox="0 0 144 144">"#,
+            r#"<image width="144" height="144" xlink:href="data:image/png;base64,{1}"/>"#,
+        ),
+        size,
+        base64(artwork)
+    );
+    if let Some(volume) = look.volume {
+        let width = 120.0 * f32::from(volume) / VOLUME_STEPS;
+        svg.push_str(&format!(
+            concat!(
+                r##"<rect x="12" y="124" width="120" height="10" rx="5" fill="#000" fill-opacity="0.6"/>"##,
+                r##"<rect x="12" y="124" width="{:.1}" height="10" rx="5" fill="{}"/>"##,
+            ),
+            width,
+            if look.muted { "#808080" } else { "#f0f0f0" }
+        ));
+    }
+    if look.sbx {
+        svg.push_str(&badge(8, "#990000", "SBX"));
+    }
+    if look.muted {
+        svg.push_str(&badge(92, "#404040", "MUTE"));
+    }
+    svg.push_str("</svg>");
+    format!("data:image/svg+xml;base64,{}", base64(svg.as_bytes()))
+}
+
+/// Rendered images, so that each one is only drawn once.
+#[derive(Default)]
+pub struct Images {
+    rendered: HashMap<(Look, u32), String>,
+}
+
+impl Images {
+    pub fn get(&mut self, look: &Look, size: u32) -> String {
+        self.rendered
+            .entry((*look, size))
+            .or_insert_with(|| render(look, size))
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_pads_short_tails() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+        assert_eq!(base64(b"fooba"), "Zm9vYmE=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn base64_uses_the_whole_alphabet() {
+        assert_eq!(base64(&[0x00, 0x10, 0x83]), "ABCD");
+        assert_eq!(base64(&[0xfb, 0xff, 0xbf]), "+/+/");
+        assert_eq!(base64(&[0xff]), "/w==");
+        assert_eq!(base64(&[0xff, 0xff]), "//8=");
+    }
+
+    #[test]
+    fn key_sizes_follow_the_deck() {
+        assert_eq!(key_size(5, 3, 1), 72);
+        assert_eq!(key_size(5, 3, 2), 144);
+        assert_eq!(key_size(3, 2, 1), 80);
+        assert_eq!(key_size(8, 4, 1), 96);
+        assert_eq!(key_size(4, 2, 2), 240);
+        // an unknown ratio is treated as 1
+        assert_eq!(key_size(5, 3, 0), 72);
+    }
+}
@@ -2,63 +2,86 @@
 compile_error!("This crate must be built for x86 for compatibility with sound drivers." +
     "(build for i686-pc-windows-msvc or suppress this error using feature ctsndcr_ignore_arch)");
 
+mod connection;
+mod device;
+mod error;
+mod image;
+mod mirror;
+mod save;
 mod sb;
 mod settings;
+mod title;
 mod types;
 
+use crate::connection::{DialMessage, DialMessageOut, InMessage, OutMessage};
+use crate::device::{Device, Retry};
+use crate::error::Error;
+use crate::image::{Images, Look};
+use crate::mirror::{Features, Mirror};
 use crate::types::*;
-use common::SerdeCardSettings;
-use futures::channel::mpsc;
+use futures::channel::{mpsc, oneshot};
+use futures::future;
 use futures::prelude::*;
-use sb::ChangeEvent;
-use slog::{crit, debug, error, info, o, warn, Drain, Logger};
-use std::collections::BTreeSet;
-use std::env;
-use std::sync::{Arc, Mutex};
+use sb::{ChangeEvent, Endpoint, SoundCoreChangeEvent, WatchEvent};
+use sbz_switch::soundcore::SoundCoreParamValue;
+use serde::de::DeserializeOwned;
+use slog::{debug, error, info, o, warn, Drain, Logger};
+use std::collections::BTreeMap;
 use std::time::Duration;
+use std::{env, mem};
 use streamdeck_rs::logging::StreamDeckDrain;
 use streamdeck_rs::registration::RegistrationParams;
-use streamdeck_rs::socket::{ConnectError, StreamDeckSocket};
-use streamdeck_rs::{KeyPayload, Message, MessageOut, StatePayload};
+use streamdeck_rs::{
+    ImagePayload, KeyPayload, Message, MessageOut, StatePayload, Target, TitlePayload,
+};
+use tokio::sync::watch;
+use tokio::time::{self, Instant};
 
-const ACTION_SELECT_OUTPUT: &str = "io.github.mdonoughe.sbzdeck.selectoutput";
-
-async fn connect(
-    params: &RegistrationParams,
-) -> Result<StreamDeckSocket<SerdeCardSettings, Empty, FromInspector, ToInspector>, ConnectError> {
-    StreamDeckSocket::<SerdeCardSettings, Empty, FromInspector, ToInspector>::connect(
-        params.port,
-        params.event.to_string(),
-        params.uuid.to_string(),
-    )
-    .await
-}
+/// How often to look for a sound card that has gone away.
+const PROBE_INTERVAL: Duration = Duration::from_secs(5);
+/// How often to check which playback device is the default while keys are
+/// showing.
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
 
 async fn handle_new_action(
     logger: &Logger,
-    state: &State,
+    state: &mut RawState,
+    action: Action,
     context: &str,
+    device: &str,
+    settings: &serde_json::Value,
     action_state: Option<u8>,
 ) {
-    let output = {
-        let mut state = state.lock().unwrap();
-        state.contexts.insert(context.to_owned());
-        state.output
-    };
-    match output {
+    state.detached.remove(context);
+    state
+        .contexts
+        .insert(context.to_owned(), Key::new(action, device, settings));
+    state.keys_changed();
+    if action == Action::Dial && !state.paused {
+        // needed to show how far a parameter is through its range
+        if let Err(error) = refresh_catalog(state).await {
+            debug!(logger, "could not read the parameter ranges: {}", error);
+        }
+    }
+    if action == Action::CycleParameter && !state.paused {
+        // needed to show the label of the current value
+        if let Err(error) = refresh_mirror(state).await {
+            debug!(logger, "could not read the card: {}", error);
+        }
+    }
+    update_keys(state).await;
+    if !action.shows_output() {
+        return;
+    }
+    match state.output {
         Some(output) if Some(Into::<u8>::into(output)) != action_state => {
             debug!(logger, "Correcting state to {:?}", output);
-            let mut state = state.lock().unwrap();
-            state
-                .out
-                .send(MessageOut::SetState {
-                    context: context.to_owned(),
-                    payload: StatePayload {
-                        state: output.into(),
-                    },
-                })
-                .await
-                .expect("failed to queue message");
+            state.send(OutMessage::Sdk(MessageOut::SetState {
+                context: context.to_owned(),
+                payload: StatePayload {
+                    state: output.into(),
+                },
+            }));
         }
         Some(_) => {
             debug!(logger, "Current state matches button state");
@@ -69,28 +92,1117 @@ async fn handle_new_action(
     }
 }
 
-fn handle_remove_action(state: &State, context: &str) {
-    let mut state = state.lock().unwrap();
+async fn announce_output(state: &mut RawState, output: Output) {
+    state.output = Some(output);
+    for (context, _) in state
+        .contexts
+        .iter()
+        .filter(|(_, key)| key.action.shows_output())
+    {
+        state.send(OutMessage::Sdk(MessageOut::SetState {
+            context: context.to_owned(),
+            payload: StatePayload {
+                state: output.into(),
+            },
+        }));
+    }
+    update_keys(state).await;
+}
+
+/// The settings of the current output, as far as they are known.
+fn shown_profile(state: &RawState) -> Option<&Profile> {
+    match state.mirror {
+        Some(ref mirror) => Some(mirror.current()),
+        None => state.output.map(|output| &state.settings.profiles[output]),
+    }
+}
+
+/// The live title a key should have, or `None` for the one chosen in the
+/// Stream Deck software.
+fn live_title(state: &RawState, key: &Key) -> Option<String> {
+    if key.custom_title || !key.show_title {
+        return None;
+    }
+    if key.action == Action::CycleParameter {
+        return cycle_title(state, key);
+    }
+    let settings: SelectOutputSettings =
+        serde_json::from_value(key.settings.clone()).unwrap_or_default();
+    if settings.title.is_empty() {
+        return None;
+    }
+    Some(title::render(
+        &settings.title,
+        state.output,
+        shown_profile(state),
+    ))
+}
+
+/// The label of the value a Cycle Parameter key's parameter has, or the
+/// value itself if it is not one of the key's values.
+fn cycle_title(state: &RawState, key: &Key) -> Option<String> {
+    let settings: CycleSettings = serde_json::from_value(key.settings.clone()).unwrap_or_default();
+    let value = shown_profile(state)
+        .and_then(|profile| profile.parameters.get(&settings.feature))
+        .and_then(|feature| feature.get(&settings.parameter))?;
+    let label = match *value {
+        SoundCoreParamValue::U32(n) => settings
+            .values
+            .iter()
+            .find(|value| value.value == n && !value.label.is_empty())
+            .map(|value| value.label.clone()),
+        _ => None,
+    };
+    Some(label.unwrap_or_else(|| title::format_value(value)))
+}
+
+/// What a key's image should show, or `None` for the image chosen in the
+/// Stream Deck software.
+fn live_image(state: &RawState, key: &Key) -> Option<Look> {
+    if state.paused || state.last_error.is_some() {
+        return None;
+    }
+    let settings: SelectOutputSettings =
+        serde_json::from_value(key.settings.clone()).unwrap_or_default();
+    if !settings.image {
+        return None;
+    }
+    let output = key.action.fixed_output().or(state.output)?;
+    // one-way keys show the volume of the output they select
+    let profile = if Some(output) == state.output {
+        shown_profile(state)
+    } else {
+        Some(&state.settings.profiles[output])
+    };
+    let sbx = matches!(
+        profile
+            .and_then(|profile| profile.parameters.get("SBX Pro Studio"))
+            .and_then(|feature| feature.get("SBX Pro Studio")),
+        Some(SoundCoreParamValue::Bool(true))
+    );
+    Some(Look::new(
+        output,
+        profile.and_then(|profile| profile.volume),
+        state.muted.unwrap_or(false),
+        sbx,
+    ))
+}
+
+async fn update_images(state: &mut RawState) {
+    let changed = state
+        .contexts
+        .iter()
+        .filter_map(|(context, key)| {
+            let look = live_image(state, key);
+            if look == key.image {
+                None
+            } else {
+                Some((context.to_owned(), look, state.image_size(&key.device)))
+            }
+        })
+        .collect::<Vec<_>>();
+    for (context, look, size) in changed {
+        if let Some(key) = state.contexts.get_mut(&context) {
+            key.image = look;
+        }
+        let image = look.map(|look| state.images.get(&look, size));
+        state.send(OutMessage::Sdk(MessageOut::SetImage {
+            context,
+            payload: ImagePayload {
+                image,
+                target: Target::Both,
+            },
+        }));
+    }
+}
+
+/// What a dial's touch strip should show, using the items of the `$B1` layout.
+fn live_feedback(state: &RawState, key: &Key) -> Option<serde_json::Value> {
+    if key.action != Action::Dial {
+        return None;
+    }
+    let settings: DialSettings = serde_json::from_value(key.settings.clone()).unwrap_or_default();
+    let profile = shown_profile(state);
+    let (title, value, fraction) = match settings.target {
+        DialTarget::Volume => {
+            let volume = profile.and_then(|profile| profile.volume);
+            let value = match volume {
+                _ if state.muted == Some(true) => "Muted".to_owned(),
+                Some(volume) => format!("{:.0}%", volume * 100.0),
+                None => "?".to_owned(),
+            };
+            ("Volume".to_owned(), value, volume)
+        }
+        DialTarget::Parameter => {
+            let value = profile
+                .and_then(|profile| profile.parameters.get(&settings.feature))
+                .and_then(|feature| feature.get(&settings.parameter));
+            let range = parameter_info(state, &settings.feature, &settings.parameter)
+                .and_then(ParameterInfo::range);
+            let fraction = match (value, range) {
+                (Some(SoundCoreParamValue::Float(value)), Some((min, max))) if max > min => {
+                    Some(((f64::from(*value) - min) / (max - min)) as f32)
+                }
+                _ => None,
+            };
+            let value = value
+                .map(title::format_value)
+                .unwrap_or_else(|| "?".to_owned());
+            (settings.parameter, value, fraction)
+        }
+    };
+    let mut feedback = serde_json::json!({ "title": title, "value": value });
+    if let Some(fraction) = fraction {
+        feedback["indicator"] = serde_json::json!({ "value": (fraction * 100.0).round() });
+    }
+    Some(feedback)
+}
+
+async fn update_feedback(state: &mut RawState) {
+    let changed = state
+        .contexts
+        .iter()
+        .filter_map(|(context, key)| {
+            let feedback = live_feedback(state, key)?;
+            if Some(&feedback) == key.feedback.as_ref() {
+                None
+            } else {
+                Some((context.to_owned(), feedback))
+            }
+        })
+        .collect::<Vec<_>>();
+    for (context, feedback) in changed {
+        if let Some(key) = state.contexts.get_mut(&context) {
+            key.feedback = Some(feedback.clone());
+        }
+        state.send(OutMessage::Dial(DialMessageOut::SetFeedback {
+            context,
+            payload: feedback,
+        }));
+    }
+}
+
+/// Bring the live titles, images and dials up to date with the sound card.
+///
+/// These are only sent when they change, so this is cheap to call after
+/// every change.
+async fn update_keys(state: &mut RawState) {
+    update_images(state).await;
+    if state.paused || state.last_error.is_some() {
+        // the titles are showing what is wrong instead
+        return;
+    }
+    update_feedback(state).await;
+    let changed = state
+        .contexts
+        .iter()
+        .filter_map(|(context, key)| {
+            let title = live_title(state, key);
+            if title == key.title {
+                None
+            } else {
+                Some((context.to_owned(), title))
+            }
+        })
+        .collect::<Vec<_>>();
+    for (context, title) in changed {
+        set_title(state, &context, title.as_deref()).await;
+    }
+}
+
+async fn send_to_inspector(state: &mut RawState, payload: ToInspector) {
+    let message = match state.inspector {
+        Some(ref inspector) => MessageOut::SendToPropertyInspector {
+            action: inspector.action.to_owned(),
+            context: inspector.context.to_owned(),
+            payload,
+        },
+        None => return,
+    };
+    state.send(OutMessage::Sdk(message));
+}
+
+async fn update_inspector_status(state: &mut RawState) {
+    let status = PluginStatus {
+        is_watching: state.watcher == WatcherStatus::Running,
+        last_error: state.last_error.clone(),
+        paused_for: state
+            .default_endpoint
+            .as_ref()
+            .filter(|_| state.paused)
+            .map(|endpoint| endpoint.name.to_owned()),
+    };
+    send_to_inspector(state, ToInspector::SetStatus { status }).await;
+}
+
+async fn update_inspector_quarantine(state: &mut RawState) {
+    let quarantine = state.quarantine.as_ref().map(|quarantined| Quarantine {
+        error: quarantined.error.to_owned(),
+        settings: serde_json::to_string_pretty(&quarantined.settings).unwrap_or_default(),
+    });
+    send_to_inspector(state, ToInspector::SetQuarantine { quarantine }).await;
+}
+
+/// Combine the stored settings with what has been seen since startup.
+fn finish_loading(state: &mut RawState, stored: CardSettings) {
+    let observed = mem::take(&mut state.settings);
+    let mut settings = settings::merge(stored, observed);
+    for (output, event) in state.early_events.drain(..) {
+        settings.profiles[output].record(event);
+    }
+    state.settings = settings;
+    state.settings_loaded = true;
+    state.reattach.set_card(state.card());
+}
+
+async fn set_title(state: &mut RawState, context: &str, title: Option<&str>) {
+    if let Some(key) = state.contexts.get_mut(context) {
+        key.title = title.map(str::to_owned);
+        if let Some(title) = title {
+            key.title_sent(title);
+        }
+    }
+    state.send(OutMessage::Sdk(MessageOut::SetTitle {
+        context: context.to_owned(),
+        payload: TitlePayload {
+            title: title.map(str::to_owned),
+            target: Target::Both,
+        },
+    }));
+}
+
+/// Log an error and show it in the property inspector, and on the key that
+/// ran into it, if any.
+async fn report_error(logger: &Logger, state: &mut RawState, error: Error, context: Option<&str>) {
+    error!(logger, "{}", error);
+    if error.invalidates_device() {
+        state.mirror = None;
+    }
+    if let Error::DeviceNotFound = error {
+        start_probe(logger, state);
+    }
+    if let Error::Unsupported = error {
+        state.output = None;
+    }
+    if let Some(context) = context {
+        set_title(state, context, Some(error.key_title())).await;
+        state.send(OutMessage::Sdk(MessageOut::ShowAlert {
+            context: context.to_owned(),
+        }));
+    }
+    state.last_error = Some(PluginError {
+        kind: error.kind(),
+        message: error.to_string(),
+    });
+    update_inspector_status(state).await;
+}
+
+/// Check for the sound card every so often until it comes back.
+fn start_probe(logger: &Logger, state: &mut RawState) {
+    if state.card_lost {
+        return;
+    }
+    warn!(logger, "the sound card is gone, waiting for it to return");
+    state.card_lost = true;
+    let logger = logger.clone();
+    let device = state.device.clone();
+    let card = state.card();
+    let commands = state.commands.clone();
+    tokio::spawn(async move {
+        while !commands.is_closed() {
+            time::delay_for(PROBE_INTERVAL).await;
+            match device.dump(card.clone(), Retry::once()).await {
+                Ok(mirror) => {
+                    let _ = commands.unbounded_send(Command::CardReturned(mirror));
+                    return;
+                }
+                Err(error) => debug!(logger, "sound card is still missing: {}", error),
+            }
+        }
+    });
+}
+
+async fn handle_card_returned(logger: &Logger, state: &mut RawState, mirror: Mirror) {
+    info!(logger, "the sound card is back");
+    state.card_lost = false;
+    if state.paused {
+        // picked up again once it is the default playback device
+        return;
+    }
+    // don't wait for the watcher's backoff to notice
+    state.reattach.now();
+    if state.watcher == WatcherStatus::Failed {
+        // the card has just been read, so there is nothing to resync once it starts
+        state.watcher = WatcherStatus::Starting;
+    }
+    let current = mirror.profile();
+    if mirror.muted().is_some() {
+        state.muted = mirror.muted();
+    }
+    state.mirror = Some(mirror);
+    // a different card may have been plugged in
+    state.catalog = None;
+    clear_error(state).await;
+    let (current_output, current_profile) = match current {
+        Some(current) => current,
+        None => {
+            report_error(logger, state, Error::Unsupported, None).await;
+            return;
+        }
+    };
+    match state.settings.options.on_card_return {
+        ReturnPolicy::Reapply => match state.output {
+            // a switch that is already running will apply everything anyway
+            Some(output) if state.switch.running.is_none() => {
+                info!(logger, "applying the {:?} profile again", output);
+                apply_in_background(state, output, None, true);
+            }
+            _ => announce_output(state, current_output).await,
+        },
+        ReturnPolicy::Recapture => {
+            info!(logger, "capturing the {:?} profile", current_output);
+            state.settings.profiles[current_output] = current_profile;
+            announce_output(state, current_output).await;
+            state.save_later();
+        }
+    }
+}
+
+async fn handle_default_endpoint(logger: &Logger, state: &mut RawState, endpoint: Endpoint) {
+    info!(logger, "the default playback device is {}", endpoint.name);
+    if state.default_endpoint.replace(endpoint).is_some() {
+        // anything mirrored may belong to the previous default
+        state.mirror = None;
+        state.catalog = None;
+    }
+    follow_default(logger, state).await;
+}
+
+/// Pause while the default playback device is not the sound card, and resume
+/// once it is again.
+///
+/// Everything the plugin does goes to the default playback device, so while
+/// some other device is the default, nothing may be captured or applied.
+async fn follow_default(logger: &Logger, state: &mut RawState) {
+    let endpoint = match state.default_endpoint {
+        Some(ref endpoint) => endpoint.clone(),
+        None => return,
+    };
+    let card = match state.settings.card {
+        Some(ref card) => card.to_owned(),
+        None => {
+            // the first device that can switch outputs is the sound card
+            if current_profile(state).await.is_ok() {
+                info!(logger, "using {} as the sound card", endpoint.name);
+                state.settings.card = Some(endpoint.id);
+                state.reattach.set_card(state.card());
+                state.save_later();
+            }
+            return;
+        }
+    };
+    let paused = endpoint.id != card;
+    if paused == state.paused {
+        return;
+    }
+    state.paused = paused;
+    let contexts = state.contexts.keys().cloned().collect::<Vec<_>>();
+    if paused {
+        warn!(
+            logger,
+            "pausing while {} is the default playback device", endpoint.name
+        );
+        state.mirror = None;
+        for context in contexts {
+            set_title(state, &context, Some("Other\ndevice")).await;
+        }
+    } else {
+        info!(
+            logger,
+            "the sound card is the default playback device again"
+        );
+        for context in contexts {
+            set_title(state, &context, None).await;
+        }
+        // anything that went wrong was about the other device
+        state.last_error = None;
+        // a watcher started while the other device was the default has failed
+        state.reattach.now();
+        resync(logger, state).await;
+    }
+    update_inspector_status(state).await;
+}
+
+/// Forget the last error now that the sound card is working again.
+async fn clear_error(state: &mut RawState) {
+    if state.last_error.take().is_none() {
+        return;
+    }
+    // put back the live titles, or the ones chosen in the Stream Deck software
+    update_keys(state).await;
+    update_inspector_status(state).await;
+}
+
+/// Make sure there is an up to date mirror of the sound card, dumping it if needed.
+async fn refresh_mirror(state: &mut RawState) -> Result<&Mirror, Error> {
+    // changes are being missed, so whatever was mirrored may be stale
+    if state.watcher == WatcherStatus::Failed {
+        state.mirror = None;
+    }
+    if state.mirror.is_none() {
+        let mirror = state.device.dump(state.card(), state.retry()).await?;
+        if mirror.muted().is_some() {
+            state.muted = mirror.muted();
+        }
+        state.mirror = Some(mirror);
+    }
+    Ok(state.mirror.as_ref().unwrap())
+}
+
+/// The active output and everything that would be saved for it.
+async fn current_profile(state: &mut RawState) -> Result<(Output, Profile), Error> {
+    refresh_mirror(state)
+        .await?
+        .profile()
+        .ok_or(Error::Unsupported)
+}
+
+/// Make sure the parameter ranges have been read.
+async fn refresh_catalog(state: &mut RawState) -> Result<&Catalog, Error> {
+    if state.catalog.is_none() {
+        state.catalog = Some(state.device.catalog(state.card(), state.retry()).await?);
+    }
+    Ok(state.catalog.as_ref().unwrap())
+}
+
+/// What each parameter accepts, or nothing if it cannot be read.
+async fn available_catalog(logger: &Logger, state: &mut RawState) -> Catalog {
+    if state.paused {
+        return Catalog::new();
+    }
+    match refresh_catalog(state).await {
+        Ok(catalog) => catalog.clone(),
+        Err(error) => {
+            warn!(logger, "could not read the parameter ranges: {}", error);
+            Catalog::new()
+        }
+    }
+}
+
+/// The SoundCore parameters the card has, or none if it cannot be read.
+async fn available_features(logger: &Logger, state: &mut RawState) -> Features {
+    if state.paused {
+        return Features::new();
+    }
+    match refresh_mirror(state).await {
+        Ok(mirror) => mirror.features().clone(),
+        Err(error) => {
+            report_error(logger, state, error, None).await;
+            Features::new()
+        }
+    }
+}
+
+/// The playback devices a key can be set to switch, or none if they cannot be listed.
+async fn available_cards(logger: &Logger, state: &RawState) -> Vec<Card> {
+    match state.device.endpoints().await {
+        Ok(endpoints) => endpoints
+            .into_iter()
+            .map(|endpoint| Card {
+                id: endpoint.id,
+                name: endpoint.name,
+            })
+            .collect(),
+        Err(error) => {
+            warn!(logger, "could not list playback devices: {}", error);
+            Vec::new()
+        }
+    }
+}
+
+async fn handle_watcher_started(logger: &Logger, state: &mut RawState) {
+    let previous = mem::replace(&mut state.watcher, WatcherStatus::Running);
+    info!(logger, "watching for changes");
+    if previous == WatcherStatus::Failed {
+        // the output may have changed while nobody was looking
+        resync(logger, state).await;
+    }
+    update_inspector_status(state).await;
+}
+
+async fn handle_watcher_failed(logger: &Logger, state: &mut RawState) {
+    if state.watcher == WatcherStatus::Failed {
+        return;
+    }
+    warn!(logger, "no longer watching for changes");
+    state.watcher = WatcherStatus::Failed;
+    state.mirror = None;
+    // find out whether the card itself has gone away
+    if !state.paused {
+        if let Err(error) = refresh_mirror(state).await {
+            report_error(logger, state, error, None).await;
+        }
+    }
+    for context in state.contexts.keys() {
+        state.send(OutMessage::Sdk(MessageOut::ShowAlert {
+            context: context.to_owned(),
+        }));
+    }
+    update_inspector_status(state).await;
+}
+
+fn handle_remove_action(state: &mut RawState, context: &str) {
     state.contexts.remove(context);
+    state.detached.remove(context);
+    state.switch.last_press.remove(context);
+    state.keys_changed();
 }
 
-async fn handle_press(
+/// Read the card again and make every key show what it is set to.
+async fn resync(logger: &Logger, state: &mut RawState) {
+    state.mirror = None;
+    if state.paused {
+        // this happens once the sound card is the default again
+        return;
+    }
+    match current_profile(state).await {
+        Ok((output, _)) => {
+            info!(logger, "detected current output to be {:?}", output);
+            announce_output(state, output).await;
+        }
+        Err(error) => report_error(logger, state, error, None).await,
+    }
+}
+
+async fn handle_device_connected(logger: &Logger, state: &mut RawState, device: &str) {
+    let restored = state
+        .detached
+        .iter()
+        .filter(|(_, key)| key.device == device)
+        .map(|(context, _)| context.to_owned())
+        .collect::<Vec<_>>();
+    debug!(logger, "restoring {} keys on {}", restored.len(), device);
+    for context in restored {
+        if let Some(key) = state.detached.remove(&context) {
+            state.contexts.insert(context, key);
+        }
+    }
+    state.keys_changed();
+    // the deck may have been unplugged while the output changed
+    if let Some(output) = state.output {
+        announce_output(state, output).await;
+    }
+}
+
+fn handle_device_disconnected(logger: &Logger, state: &mut RawState, device: &str) {
+    let detached = state
+        .contexts
+        .iter()
+        .filter(|(_, key)| key.device == device)
+        .map(|(context, _)| context.to_owned())
+        .collect::<Vec<_>>();
+    debug!(logger, "forgetting {} keys on {}", detached.len(), device);
+    for context in detached {
+        if let Some(key) = state.contexts.remove(&context) {
+            state.detached.insert(context, key);
+        }
+    }
+    state.keys_changed();
+}
+
+/// Set a key back to the output it should be showing after ignoring a press.
+async fn restore_key(state: &mut RawState, context: &str) {
+    let shows_output = state
+        .contexts
+        .get(context)
+        .map(|key| key.action.shows_output())
+        // keys in multi-actions never appear, but do change state
+        .unwrap_or(true);
+    if !shows_output {
+        return;
+    }
+    let output = match state.switch.target().or(state.output) {
+        Some(output) => output,
+        None => return,
+    };
+    state.send(OutMessage::Sdk(MessageOut::SetState {
+        context: context.to_owned(),
+        payload: StatePayload {
+            state: output.into(),
+        },
+    }));
+}
+
+/// What the sound card accepts for a parameter, if known.
+fn parameter_info<'a>(
+    state: &'a RawState,
+    feature: &str,
+    parameter: &str,
+) -> Option<&'a ParameterInfo> {
+    state
+        .catalog
+        .as_ref()
+        .and_then(|catalog| catalog.get(feature))
+        .and_then(|feature| feature.get(parameter))
+}
+
+/// A change to one parameter.
+fn parameter_change(feature: &str, parameter: &str, value: SoundCoreParamValue) -> ChangeEvent {
+    ChangeEvent::SoundCore(SoundCoreChangeEvent {
+        feature: feature.to_owned(),
+        parameter: parameter.to_owned(),
+        value,
+    })
+}
+
+/// Make a change asked for by a dial or key, then record it as if it had
+/// been seen on the card, so the active profile remembers it.
+async fn apply_change(logger: &Logger, state: &mut RawState, context: &str, change: ChangeEvent) {
+    if state.switch.running.is_some() {
+        debug!(logger, "holding {:?} until the switch finishes", change);
+        state.switch.deferred.push((context.to_owned(), change));
+        return;
+    }
+    match state
+        .device
+        .apply_change(state.card(), change.clone(), state.retry())
+        .await
+    {
+        Ok(()) => {
+            clear_error(state).await;
+            handle_changes(logger, vec![change], state).await;
+        }
+        Err(error) => report_error(logger, state, error, Some(context)).await,
+    }
+}
+
+async fn show_alert(state: &mut RawState, context: &str) {
+    state.send(OutMessage::Sdk(MessageOut::ShowAlert {
+        context: context.to_owned(),
+    }));
+}
+
+/// What the card accepts for a numeric parameter and what it is set to, or
+/// `None` if the card has no such numeric parameter.
+async fn numeric_parameter(
+    state: &mut RawState,
+    feature: &str,
+    parameter: &str,
+) -> Result<Option<(ParameterInfo, SoundCoreParamValue)>, Error> {
+    refresh_catalog(state).await?;
+    let info = match parameter_info(state, feature, parameter) {
+        Some(info) if info.kind != ParameterKind::Bool => info.clone(),
+        _ => return Ok(None),
+    };
+    let value = refresh_mirror(state)
+        .await?
+        .features()
+        .get(feature)
+        .and_then(|feature| feature.get(parameter))
+        .cloned();
+    Ok(value.map(|value| (info, value)))
+}
+
+/// A numeric value moved by `step` and kept within its range, as far as the
+/// card says what that is.
+fn stepped(value: &SoundCoreParamValue, info: &ParameterInfo, step: f64) -> SoundCoreParamValue {
+    let moved = |value: f64| info.clamp(value + step);
+    match *value {
+        SoundCoreParamValue::I32(n) => SoundCoreParamValue::I32(moved(f64::from(n)).round() as i32),
+        SoundCoreParamValue::U32(n) => SoundCoreParamValue::U32(moved(f64::from(n)).round() as u32),
+        SoundCoreParamValue::Float(n) => SoundCoreParamValue::Float(moved(f64::from(n)) as f32),
+        ref value => value.clone(),
+    }
+}
+
+/// Step a numeric parameter by the amount set for an Adjust Parameter key.
+async fn handle_adjust(
     logger: &Logger,
-    state: &State,
+    state: &mut RawState,
     context: &str,
-    payload: &KeyPayload<Empty>,
-    trigger_save: &mut mpsc::Sender<()>,
+    settings: &serde_json::Value,
 ) {
+    let settings: AdjustSettings = key_settings(logger, settings);
+    if state.paused {
+        warn!(logger, "not adjusting while another device is the default");
+        show_alert(state, context).await;
+        return;
+    }
+    match numeric_parameter(state, &settings.feature, &settings.parameter).await {
+        Ok(Some((info, value))) => {
+            let value = stepped(&value, &info, settings.step);
+            let change = parameter_change(&settings.feature, &settings.parameter, value);
+            apply_change(logger, state, context, change).await;
+        }
+        Ok(None) => {
+            warn!(
+                logger,
+                "the card has no numeric parameter {}/{}", settings.feature, settings.parameter
+            );
+            show_alert(state, context).await;
+        }
+        Err(error) => report_error(logger, state, error, Some(context)).await,
+    }
+}
+
+/// `value` as the kind of value a parameter holds, or `None` if the
+/// parameter does not accept it.
+fn parameter_value(info: &ParameterInfo, value: &serde_json::Value) -> Option<SoundCoreParamValue> {
+    if !info.accepts(value) {
+        return None;
+    }
+    match info.kind {
+        ParameterKind::Bool => value.as_bool().map(SoundCoreParamValue::Bool),
+        ParameterKind::I32 => value.as_i64().map(|n| SoundCoreParamValue::I32(n as i32)),
+        ParameterKind::U32 => value.as_u64().map(|n| SoundCoreParamValue::U32(n as u32)),
+        ParameterKind::Float => value.as_f64().map(|n| SoundCoreParamValue::Float(n as f32)),
+    }
+}
+
+/// Set a parameter to the value stored with a Set Parameter key.
+async fn handle_set_parameter(
+    logger: &Logger,
+    state: &mut RawState,
+    context: &str,
+    settings: &serde_json::Value,
+) {
+    let settings: SetParameterSettings = key_settings(logger, settings);
+    if state.paused {
+        warn!(
+            logger,
+            "not setting a parameter while another device is the default"
+        );
+        show_alert(state, context).await;
+        return;
+    }
+    if let Err(error) = refresh_catalog(state).await {
+        report_error(logger, state, error, Some(context)).await;
+        return;
+    }
+    let value = match (
+        parameter_info(state, &settings.feature, &settings.parameter),
+        &settings.value,
+    ) {
+        (Some(info), Some(value)) => parameter_value(info, value),
+        _ => None,
+    };
+    match value {
+        Some(value) => {
+            let change = parameter_change(&settings.feature, &settings.parameter, value);
+            apply_change(logger, state, context, change).await;
+        }
+        None => {
+            warn!(
+                logger,
+                "the card does not accept {:?} for {}/{}",
+                settings.value,
+                settings.feature,
+                settings.parameter
+            );
+            show_alert(state, context).await;
+        }
+    }
+}
+
+/// The value after `current` in a Cycle Parameter key's list, starting over
+/// from the first when `current` is the last or is not in the list.
+fn next_in_cycle(values: &[CycleValue], current: u32) -> Option<u32> {
+    let next = values
+        .iter()
+        .position(|value| value.value == current)
+        .map_or(0, |i| (i + 1) % values.len());
+    values.get(next).map(|value| value.value)
+}
+
+/// Step a parameter to the next value in a Cycle Parameter key's list.
+async fn handle_cycle(
+    logger: &Logger,
+    state: &mut RawState,
+    context: &str,
+    settings: &serde_json::Value,
+) {
+    let settings: CycleSettings = key_settings(logger, settings);
+    if state.paused {
+        warn!(logger, "not cycling while another device is the default");
+        show_alert(state, context).await;
+        return;
+    }
+    let next = match numeric_parameter(state, &settings.feature, &settings.parameter).await {
+        Ok(Some((info, SoundCoreParamValue::U32(current)))) => {
+            next_in_cycle(&settings.values, current)
+                .filter(|&next| info.accepts(&serde_json::Value::from(next)))
+        }
+        Ok(_) => None,
+        Err(error) => {
+            report_error(logger, state, error, Some(context)).await;
+            return;
+        }
+    };
+    match next {
+        Some(next) => {
+            let change = parameter_change(
+                &settings.feature,
+                &settings.parameter,
+                SoundCoreParamValue::U32(next),
+            );
+            apply_change(logger, state, context, change).await;
+        }
+        None => {
+            warn!(
+                logger,
+                "cannot cycle {}/{} through {:?}",
+                settings.feature,
+                settings.parameter,
+                settings.values
+            );
+            show_alert(state, context).await;
+        }
+    }
+}
+
+/// The change that turning a dial by `ticks` makes, or `None` if the dial is
+/// not set to a `Float` parameter the card has.
+async fn dial_turned(
+    state: &mut RawState,
+    settings: &DialSettings,
+    ticks: i32,
+) -> Result<Option<ChangeEvent>, Error> {
+    let step = settings.step / 100.0 * ticks as f32;
+    match settings.target {
+        DialTarget::Volume => {
+            let volume = refresh_mirror(state)
+                .await?
+                .current()
+                .volume
+                .unwrap_or_default();
+            Ok(Some(ChangeEvent::Volume((volume + step).max(0.0).min(1.0))))
+        }
+        DialTarget::Parameter => {
+            match numeric_parameter(state, &settings.feature, &settings.parameter).await? {
+                Some((info, value)) if info.kind == ParameterKind::Float => {
+                    // without a range, take the step as a share of 0 to 1,
+                    // which is where most Float parameters lie
+                    let span = info.range().map_or(1.0, |(min, max)| max - min);
+                    let step = f64::from(step) * span;
+                    Ok(Some(parameter_change(
+                        &settings.feature,
+                        &settings.parameter,
+                        stepped(&value, &info, step),
+                    )))
+                }
+                _ => Ok(None),
+            }
+        }
+    }
+}
+
+/// The change that pressing a dial makes, or `None` if it has nothing to
+/// reset to.
+fn dial_pressed(state: &RawState, settings: &DialSettings) -> Option<ChangeEvent> {
+    match settings.press {
+        DialPress::Mute => Some(ChangeEvent::Mute(!state.muted.unwrap_or(false))),
+        DialPress::Reset => {
+            let reset = settings.reset?;
+            match settings.target {
+                DialTarget::Volume => Some(ChangeEvent::Volume((reset / 100.0).max(0.0).min(1.0))),
+                DialTarget::Parameter => {
+                    let reset = match parameter_info(state, &settings.feature, &settings.parameter)
+                    {
+                        Some(info) => info.clamp(f64::from(reset)) as f32,
+                        None => reset,
+                    };
+                    Some(parameter_change(
+                        &settings.feature,
+                        &settings.parameter,
+                        SoundCoreParamValue::Float(reset),
+                    ))
+                }
+            }
+        }
+    }
+}
+
+async fn handle_dial(logger: &Logger, message: DialMessage, state: &mut RawState) {
+    let (context, ticks) = match message {
+        DialMessage::DialRotate {
+            context, payload, ..
+        } => (context, Some(payload.ticks)),
+        DialMessage::DialDown { context, .. } | DialMessage::TouchTap { context, .. } => {
+            (context, None)
+        }
+        // the press has already been handled on the way down
+        DialMessage::DialUp { .. } => return,
+    };
+    let settings: DialSettings = match state.contexts.get(&context) {
+        Some(key) => key_settings(logger, &key.settings),
+        None => return,
+    };
+    if state.paused {
+        warn!(logger, "ignoring the dial while paused");
+        show_alert(state, &context).await;
+        return;
+    }
+    let change = match ticks {
+        Some(ticks) => match dial_turned(state, &settings, ticks).await {
+            Ok(change) => change,
+            Err(error) => {
+                report_error(logger, state, error, Some(&context)).await;
+                return;
+            }
+        },
+        None => dial_pressed(state, &settings),
+    };
+    match change {
+        Some(change) => apply_change(logger, state, &context, change).await,
+        None => {
+            warn!(
+                logger,
+                "the dial has no Float parameter to adjust or no value to reset to"
+            );
+            show_alert(state, &context).await;
+        }
+    }
+}
+
+/// Add up the turns of a dial that queued while the last one was applied, so
+/// that a quick turn makes one change to the card instead of one per tick.
+///
+/// Also returns the first command that was read but could not be merged.
+fn merge_dial_turns(
+    message: DialMessage,
+    commands: &mut mpsc::UnboundedReceiver<Command>,
+) -> (DialMessage, Option<Command>) {
+    let (context, mut payload) = match message {
+        DialMessage::DialRotate { context, payload } => (context, payload),
+        message => return (message, None),
+    };
+    let mut next = None;
+    while let Ok(Some(command)) = commands.try_next() {
+        match command {
+            Command::Dial(DialMessage::DialRotate {
+                context: ref other,
+                payload: ref more,
+            }) if *other == context => {
+                payload.ticks = payload.ticks.saturating_add(more.ticks);
+            }
+            command => {
+                next = Some(command);
+                break;
+            }
+        }
+    }
+    (DialMessage::DialRotate { context, payload }, next)
+}
+
+/// Read the settings stored with a key or dial.
+fn key_settings<T: DeserializeOwned + Default>(logger: &Logger, settings: &serde_json::Value) -> T {
+    serde_json::from_value(settings.clone()).unwrap_or_else(|error| {
+        warn!(
+            logger,
+            "ignoring key settings that could not be read: {}", error
+        );
+        T::default()
+    })
+}
+
+/// The output a Select Output key switches to when pressed.
+fn press_output(
+    settings: &SelectOutputSettings,
+    payload: &KeyPayload<serde_json::Value>,
+) -> Output {
+    match settings.direction {
+        Direction::Headphones => return Output::Headphones,
+        Direction::Speakers => return Output::Speakers,
+        Direction::Toggle => {}
+    }
+    match (settings.headphones, settings.speakers) {
+        (true, false) => return Output::Headphones,
+        (false, true) => return Output::Speakers,
+        // toggling between nothing is taken to mean toggling between both
+        _ => {}
+    }
     let desired_state = payload
         .user_desired_state
         .unwrap_or_else(|| (payload.state.unwrap_or(0) + 1) % 2);
-    let output = match desired_state {
+    match desired_state {
         0 => Output::Headphones,
         1 => Output::Speakers,
         _ => unreachable!(),
+    }
+}
+
+async fn handle_press(
+    logger: &Logger,
+    state: &mut RawState,
+    action: Action,
+    context: &str,
+    payload: &KeyPayload<serde_json::Value>,
+) {
+    let settings: SelectOutputSettings = key_settings(logger, &payload.settings);
+    if let Some(ref card) = settings.card {
+        if state.settings.card.as_ref() != Some(card) {
+            warn!(
+                logger,
+                "not switching {}, which is not the sound card", card
+            );
+            state.send(OutMessage::Sdk(MessageOut::ShowAlert {
+                context: context.to_owned(),
+            }));
+            restore_key(state, context).await;
+            return;
+        }
+    }
+    let press = Press {
+        output: action
+            .fixed_output()
+            .unwrap_or_else(|| press_output(&settings, payload)),
+        context: context.to_owned(),
+        apply_volume: settings.apply_volume,
+        reapply: settings.reapply,
     };
 
-    let mut state = state.lock().unwrap();
+    let now = Instant::now();
+    let debounce = Duration::from_millis(state.settings.options.press_debounce_ms);
+    if let Some(&last_press) = state.switch.last_press.get(context) {
+        if now.duration_since(last_press) < debounce {
+            debug!(
+                logger,
+                "ignoring press within {:?} of the last one", debounce
+            );
+            restore_key(state, context).await;
+            return;
+        }
+    }
+    state.switch.last_press.insert(context.to_owned(), now);
+
+    if state.switch.running.is_some() {
+        // only the last press matters once the current switch finishes
+        debug!(
+            logger,
+            "will switch to {:?} after the current switch", press.output
+        );
+        state.switch.pending = Some(press);
+        return;
+    }
+    start_switch(logger, state, press).await;
+}
+
+async fn start_switch(logger: &Logger, state: &mut RawState, press: Press) {
+    let Press {
+        output,
+        context,
+        apply_volume,
+        reapply,
+    } = press;
+    if state.paused {
+        // switching would change the other device instead
+        warn!(logger, "not switching while another device is the default");
+        state.send(OutMessage::Sdk(MessageOut::ShowAlert {
+            context: context.to_owned(),
+        }));
+        restore_key(state, &context).await;
+        return;
+    }
     // save back current state
     // Why update the state right before switching even if events are being
     // monitored? Changes to the device state are not atomic, so if the user
@@ -103,104 +1215,261 @@ async fn handle_press(
     // before toggling. This means pressing the toggle key basically acts as
     // confirmation that the current settings are desired settings in the case
     // where we are not sure.
-    match sb::get_current_profile(&logger) {
-        Ok(Some((current_device_output, current_device_profile))) => {
+    match current_profile(state).await {
+        Ok((current_device_output, current_device_profile)) => {
             info!(
                 logger,
                 "detected current output to be {:?}", current_device_output
             );
             if output == current_device_output {
-                // this should only happen with multiactions
+                // this happens with multiactions, one way keys, or when presses cancel out
+                if reapply {
+                    info!(logger, "applying the {:?} profile again", output);
+                    apply_in_background(state, output, Some(context), apply_volume);
+                    return;
+                }
+                state.send(OutMessage::Sdk(MessageOut::ShowOk { context }));
+                announce_output(state, output).await;
                 return;
             }
             state.settings.profiles[current_device_output] = current_device_profile;
-            let _ = trigger_save.try_send(());
         }
-        Ok(None) => {
-            error!(
-                logger,
-                "could not find output device in sound card configuration"
-            );
+        Err(error @ Error::Win32(_)) | Err(error @ Error::Driver(_)) => {
+            // capturing is only a precaution, so switching is still worth a try
+            error!(logger, "error reading sound card configuration: {}", error);
+        }
+        Err(error) => {
+            // A missing or unsupported card cannot be switched, and anything
+            // else would only queue up behind a stuck call.
+            report_error(logger, state, error, Some(&context)).await;
+            return;
         }
-        Err(error) => error!(
-            logger,
-            "error reading sound card configuration: {:?}", error
-        ),
     }
 
-    match sb::apply_profile(
-        logger,
-        output,
-        &state.settings.profiles[output],
-        &state.settings.selected_parameters,
-    ) {
+    apply_in_background(state, output, Some(context), apply_volume);
+}
+
+/// Apply the profile for `output` without capturing anything first.
+///
+/// Applying the profile takes a while, so it happens in the background and
+/// presses that arrive meanwhile can be combined.
+fn apply_in_background(
+    state: &mut RawState,
+    output: Output,
+    context: Option<String>,
+    apply_volume: bool,
+) {
+    state.switch.running = Some(output);
+    let device = state.device.clone();
+    let mut profile = state.settings.profiles[output].clone();
+    if !apply_volume {
+        profile.volume = None;
+    }
+    let selected_parameters = state.settings.selected_parameters.clone();
+    // not whatever happens to be the default by the time the switch runs
+    let card = state.card();
+    let commands = state.commands.clone();
+    let retry = state.retry();
+    tokio::spawn(async move {
+        let result = device
+            .apply_profile(card, output, profile.clone(), selected_parameters, retry)
+            .await;
+        let _ = commands.unbounded_send(Command::Switched {
+            output,
+            profile,
+            context,
+            result,
+        });
+    });
+}
+
+async fn handle_switched(
+    logger: &Logger,
+    state: &mut RawState,
+    output: Output,
+    profile: Profile,
+    context: Option<String>,
+    result: Result<(), Error>,
+) {
+    state.switch.running = None;
+    match result {
         Ok(_) => {
             state.output = Some(output);
-            debug!(logger, "Set output to {}", desired_state);
-            state
-                .out
-                .send(MessageOut::ShowOk {
-                    context: context.to_string(),
-                })
-                .await
-                .expect("failed to queue message");
+            if let Some(ref mut mirror) = state.mirror {
+                mirror.applied(output, &profile, &state.settings.selected_parameters);
+            }
+            debug!(logger, "Set output to {:?}", output);
+            clear_error(state).await;
+            // don't risk forgetting the captured profile
+            state.saver.now();
+            update_keys(state).await;
+            match context {
+                Some(context) => state.send(OutMessage::Sdk(MessageOut::ShowOk { context })),
+                // not started by a key, so make sure they all agree
+                None => announce_output(state, output).await,
+            }
         }
         Err(error) => {
-            error!(
-                logger,
-                "Failed to set output to {}: {:?}", desired_state, error
-            );
-            state
-                .out
-                .send(MessageOut::ShowAlert {
-                    context: context.to_string(),
-                })
-                .await
-                .expect("failed to queue message")
+            error!(logger, "Failed to set output to {:?}", output);
+            // the card may have been left part way between the two profiles
+            state.mirror = None;
+            report_error(logger, state, error, context.as_deref()).await;
         }
     }
+    for (context, change) in mem::take(&mut state.switch.deferred) {
+        apply_change(logger, state, &context, change).await;
+    }
+    if let Some(press) = state.switch.pending.take() {
+        start_switch(logger, state, press).await;
+    }
 }
 
-async fn handle_message(
-    logger: &Logger,
-    message: Message<SerdeCardSettings, Empty, FromInspector>,
-    state: &State,
-    trigger_save: &mut mpsc::Sender<()>,
-) {
+async fn handle_connected(state: &mut RawState) {
+    // a new connection means a new property inspector, if any
+    state.inspector = None;
+    // the keys may have been reset if the Stream Deck software restarted
+    if let Some(output) = state.output {
+        announce_output(state, output).await;
+    }
+}
+
+async fn handle_message(logger: &Logger, message: InMessage, state: &mut RawState) {
     match message {
         Message::WillAppear {
             ref action,
             ref context,
+            ref device,
             ref payload,
             ..
-        } if action == ACTION_SELECT_OUTPUT => {
-            handle_new_action(logger, state, &context, payload.state).await
+        } => {
+            if let Some(action) = Action::from_uuid(action) {
+                handle_new_action(
+                    logger,
+                    state,
+                    action,
+                    &context,
+                    // the deck is not always given
+                    device.as_deref().unwrap_or_default(),
+                    &payload.settings,
+                    payload.state,
+                )
+                .await
+            }
         }
-        Message::WillDisappear {
-            ref action,
+        Message::WillDisappear { ref context, .. } => handle_remove_action(state, &context),
+        Message::DidReceiveSettings {
             ref context,
+            ref payload,
+            ..
+        } => {
+            if let Some(key) = state.contexts.get_mut(context) {
+                key.settings = payload.settings.clone();
+            }
+            update_keys(state).await;
+        }
+        Message::TitleParametersDidChange {
+            ref context,
+            ref payload,
             ..
-        } if action == ACTION_SELECT_OUTPUT => handle_remove_action(state, &context),
+        } => {
+            if let Some(key) = state.contexts.get_mut(context) {
+                // the title reported may be one the plugin set, even one that
+                // has already been replaced
+                key.custom_title = !payload.title.is_empty() && !key.is_sent_title(&payload.title);
+                key.show_title = payload.title_parameters.show_title;
+            }
+            update_keys(state).await;
+        }
         Message::KeyUp {
             ref action,
             ref context,
             ref payload,
             ..
-        } if action == ACTION_SELECT_OUTPUT => {
-            handle_press(logger, state, &context, &payload, trigger_save).await
+        } => match Action::from_uuid(action) {
+            Some(Action::AdjustParameter) => {
+                handle_adjust(logger, state, &context, &payload.settings).await
+            }
+            Some(Action::SetParameter) => {
+                handle_set_parameter(logger, state, &context, &payload.settings).await
+            }
+            Some(Action::CycleParameter) => {
+                handle_cycle(logger, state, &context, &payload.settings).await
+            }
+            Some(action) => handle_press(logger, state, action, &context, &payload).await,
+            None => {}
+        },
+        Message::SystemDidWakeUp { .. } => {
+            info!(logger, "system woke up");
+            // anything could have happened to the card while asleep
+            resync(logger, state).await;
+        }
+        Message::DeviceDidConnect {
+            ref device,
+            ref device_info,
+            ..
+        } => {
+            let size = image::key_size(
+                device_info.size.columns,
+                device_info.size.rows,
+                state.pixel_ratio,
+            );
+            state.image_sizes.insert(device.to_owned(), size);
+            handle_device_connected(logger, state, &device).await
+        }
+        Message::DeviceDidDisconnect { ref device, .. } => {
+            handle_device_disconnected(logger, state, &device)
         }
         Message::DidReceiveGlobalSettings { payload, .. } => {
-            match settings::load(payload.settings) {
+            if state.settings_loaded && state.quarantine.is_none() {
+                // This happens after reconnecting. Everything since the first
+                // load has been seen by the plugin, and anything not yet saved
+                // is newer than what the Stream Deck software has.
+                debug!(logger, "ignoring stored settings");
+                return;
+            }
+            match settings::load(payload.settings.clone()) {
                 Ok(settings) => {
-                    let mut state = state.lock().unwrap();
-                    state.settings = settings;
+                    finish_loading(state, settings);
                     info!(logger, "loaded settings");
+                    if state.quarantine.take().is_some() {
+                        update_inspector_quarantine(state).await;
+                    }
+                    // the merged settings may differ from what is stored
+                    state.save_later();
+                    // the stored sound card may not be the default
+                    follow_default(logger, state).await;
                 }
                 Err(error) => {
-                    error!(logger, "error loading settings: {:?}", error);
+                    let error = Error::SettingsInvalid(error.to_string());
+                    error!(logger, "{}", error);
+                    // Keep working with what can be observed, but the stored
+                    // settings must not be replaced until the user decides
+                    // what to do with them.
+                    if !state.settings_loaded {
+                        finish_loading(state, CardSettings::default());
+                    }
+                    state.quarantine = Some(Quarantined {
+                        settings: payload.settings,
+                        error: error.to_string(),
+                    });
+                    // the property inspector explains what to do about it
+                    for context in state.contexts.keys().cloned().collect::<Vec<_>>() {
+                        state.send(OutMessage::Sdk(MessageOut::ShowAlert { context }));
+                    }
+                    update_inspector_quarantine(state).await;
                 }
             }
         }
+        Message::PropertyInspectorDidDisappear { ref context, .. } => {
+            if state
+                .inspector
+                .as_ref()
+                .map(|inspector| &inspector.context == context)
+                .unwrap_or_default()
+            {
+                state.inspector = None;
+            }
+        }
         Message::SendToPlugin {
             action,
             context,
@@ -208,11 +1477,8 @@ async fn handle_message(
             ..
         } => match payload {
             FromInspector::GetFeatures => {
-                let available = sbz_switch::dump(&logger, None)
-                    .ok()
-                    .and_then(|s| s.creative)
-                    .unwrap_or_default();
-                let mut state = state.lock().unwrap();
+                let available = available_features(logger, state).await;
+                state.inspector = Some(Inspector { action, context });
                 let response = available
                     .into_iter()
                     .map(|(k, v)| {
@@ -230,26 +1496,31 @@ async fn handle_message(
                         )
                     })
                     .collect();
-                state
-                    .out
-                    .send(MessageOut::SendToPropertyInspector {
-                        action,
-                        context,
-                        payload: ToInspector::SetFeatures {
-                            selected_parameters: response,
-                        },
-                    })
-                    .await
-                    .expect("failed to queue message");
+                send_to_inspector(
+                    state,
+                    ToInspector::SetFeatures {
+                        selected_parameters: response,
+                    },
+                )
+                .await;
+                let options = state.settings.options.clone();
+                send_to_inspector(state, ToInspector::SetOptions { options }).await;
+                let cards = available_cards(logger, state).await;
+                send_to_inspector(state, ToInspector::SetCards { cards }).await;
+                let catalog = available_catalog(logger, state).await;
+                send_to_inspector(state, ToInspector::SetCatalog { catalog }).await;
+                update_inspector_status(state).await;
+                update_inspector_quarantine(state).await;
             }
             FromInspector::SetFeatures {
                 selected_parameters,
             } => {
-                let available = sbz_switch::dump(&logger, None)
-                    .ok()
-                    .and_then(|s| s.creative)
-                    .unwrap_or_default();
-                let mut state = state.lock().unwrap();
+                if state.paused {
+                    // the parameters of the other device are unknown
+                    warn!(logger, "not changing the selection while paused");
+                    return;
+                }
+                let available = available_features(logger, state).await;
                 state.settings.selected_parameters = available
                     .into_iter()
                     .filter_map(|(k, v)| {
@@ -268,171 +1539,534 @@ async fn handle_message(
                     logger,
                     "selecting features are now {:?}", state.settings.selected_parameters
                 );
-                let _ = trigger_save.try_send(());
+                state.save_later();
+            }
+            FromInspector::SetOptions { options } => {
+                info!(logger, "options are now {:?}", options);
+                state.settings.options = options;
+                state.save_later();
+            }
+            FromInspector::Recover { recovery } => {
+                let quarantined = match state.quarantine.take() {
+                    Some(quarantined) => quarantined,
+                    None => return,
+                };
+                match recovery {
+                    Recovery::Salvage => {
+                        info!(logger, "recovering what can be read from stored settings");
+                        finish_loading(state, settings::salvage(&quarantined.settings));
+                    }
+                    Recovery::Discard => {
+                        warn!(logger, "discarding stored settings");
+                    }
+                }
+                update_inspector_quarantine(state).await;
+                state.saver.now();
+                follow_default(logger, state).await;
             }
         },
         _ => {}
     }
 }
 
+/// Apply a batch of changes seen on the sound card.
+///
+/// However many changes there are, the keys are updated and a save is
+/// requested at most once.
+async fn handle_changes(logger: &Logger, changes: Vec<ChangeEvent>, state: &mut RawState) {
+    debug!(logger, "saw changes: {:?}", changes);
+    if state.paused {
+        debug!(logger, "ignoring changes while paused");
+        return;
+    }
+    let mut output_reported = false;
+    for change in changes {
+        if let Some(ref mut mirror) = state.mirror {
+            mirror.record(change.clone());
+        }
+        match change {
+            ChangeEvent::SoundCore(ref event)
+                if event.feature == "Device Control" && event.parameter == "SelectOutput" =>
+            {
+                output_reported = true;
+                state.output = Output::try_from(&event.value);
+                if state.output.is_none() {
+                    warn!(
+                        logger,
+                        "output device changed to unrecognized value {:?}", event.value
+                    );
+                }
+            }
+            ChangeEvent::Mute(muted) => state.muted = Some(muted),
+            change => {
+                if let Some(output) = state.output {
+                    if state.settings_loaded {
+                        // Why update the profile here if we update the profile again right
+                        // before switching? If the user changes a setting and then
+                        // manually switches outputs, we want to capture that setting for
+                        // the next time the user switches back to the original output.
+                        state.settings.profiles[output].record(change);
+                    } else {
+                        // replayed on top of the stored settings once they arrive
+                        state.early_events.push((output, change));
+                    }
+                }
+            }
+        }
+    }
+    if output_reported {
+        if let Some(output) = state.output {
+            announce_output(state, output).await;
+        }
+    }
+    update_keys(state).await;
+    state.save_later();
+}
+
+async fn handle_watch_event(logger: &Logger, event: WatchEvent, state: &mut RawState) {
+    match event {
+        WatchEvent::Started => handle_watcher_started(logger, state).await,
+        WatchEvent::Failed => handle_watcher_failed(logger, state).await,
+        WatchEvent::Changes(changes) => handle_changes(logger, changes, state).await,
+    }
+}
+
+/// Own the plugin state, handling commands one at a time until every sender is gone.
+async fn run_state(
+    logger: Logger,
+    mut state: RawState,
+    mut commands: mpsc::UnboundedReceiver<Command>,
+) {
+    // a command read ahead while merging dial turns
+    let mut next = None;
+    loop {
+        let command = match next.take() {
+            Some(command) => command,
+            None => match commands.next().await {
+                Some(command) => command,
+                None => return,
+            },
+        };
+        match command {
+            Command::Connected => handle_connected(&mut state).await,
+            Command::Message(message) => {
+                debug!(logger, "received {:?}", message);
+                handle_message(&logger, message, &mut state).await;
+            }
+            Command::Dial(message) => {
+                debug!(logger, "received {:?}", message);
+                let (message, after) = merge_dial_turns(message, &mut commands);
+                next = after;
+                handle_dial(&logger, message, &mut state).await;
+            }
+            Command::Watch(event) => handle_watch_event(&logger, event, &mut state).await,
+            Command::Switched {
+                output,
+                profile,
+                context,
+                result,
+            } => handle_switched(&logger, &mut state, output, profile, context, result).await,
+            Command::DefaultEndpoint(endpoint) => {
+                handle_default_endpoint(&logger, &mut state, endpoint).await
+            }
+            Command::CardReturned(mirror) => {
+                handle_card_returned(&logger, &mut state, mirror).await
+            }
+            Command::Snapshot(reply) => {
+                let _ = reply.send(save::snapshot(&logger, &state));
+            }
+            Command::Stop(reply) => {
+                debug!(logger, "stopping");
+                // this releases the state's sender, so nothing more can be queued
+                drop(state);
+                let _ = reply.send(());
+                return;
+            }
+        }
+    }
+}
+
 #[tokio::main(max_threads=1)]
 async fn main() {
     let params = &RegistrationParams::from_args(env::args()).unwrap();
 
-    let (mut out_sink, mut out_stream) = mpsc::channel(2);
+    let (out_sink, out_stream) = mpsc::unbounded();
+    let (saver, save_requests) = save::channel();
+    let (log_sink, mut log_stream) = mpsc::unbounded();
+    let logger = slog::Logger::root(StreamDeckDrain::new(log_sink).fuse(), o!());
+    let device = Device::spawn(&logger);
+    let (mut events, reattach) = sb::watch(&logger);
+    let (commands, command_stream) = mpsc::unbounded();
+    let (keys_shown, mut keys_shown_rx) = watch::channel(false);
     let mut state = RawState {
         output: None,
-        contexts: BTreeSet::new(),
+        contexts: BTreeMap::new(),
+        detached: BTreeMap::new(),
+        watcher: WatcherStatus::Starting,
+        inspector: None,
         out: out_sink.clone(),
+        saver: saver.clone(),
+        device: device.clone(),
+        commands: commands.clone(),
+        switch: Switching::default(),
+        last_error: None,
+        card_lost: false,
+        reattach,
+        default_endpoint: None,
+        keys_shown,
+        paused: false,
+        muted: None,
+        images: Images::default(),
+        image_sizes: params
+            .info
+            .devices
+            .iter()
+            .map(|device| {
+                let size = image::key_size(
+                    device.size.columns,
+                    device.size.rows,
+                    params.info.device_pixel_ratio,
+                );
+                (device.id.clone(), size)
+            })
+            .collect(),
+        pixel_ratio: params.info.device_pixel_ratio,
+        mirror: None,
+        catalog: None,
         settings: CardSettings::default(),
+        settings_loaded: false,
+        early_events: Vec::new(),
+        quarantine: None,
     };
 
-    let (log_sink, mut log_stream) = mpsc::unbounded();
-    let mut log_out_sink = out_sink.clone();
+    let log_out_sink = out_sink.clone();
     let log_task = async {
         while let Some(evt) = log_stream.next().await {
-            log_out_sink.send(evt).await.expect("failed to forward log")
+            if log_out_sink.unbounded_send(OutMessage::Sdk(evt)).is_err() {
+                // shutting down
+                break;
+            }
         }
     };
 
-    let logger = slog::Logger::root(StreamDeckDrain::new(log_sink).fuse(), o!());
-
-    match sb::get_current_profile(&logger) {
-        Ok(Some((output, profile))) => {
+    match current_profile(&mut state).await {
+        Ok((output, profile)) => {
             info!(logger, "detected current output to be {:?}", output);
             state.output = Some(output);
             state.settings.profiles[output] = profile;
         }
-        Ok(None) => {
-            error!(
-                logger,
-                "could not find output device in sound card configuration"
-            );
-        }
-        Err(error) => error!(
-            logger,
-            "error reading sound card configuration: {:?}", error
-        ),
-    }
-
-    let state = Arc::new(Mutex::new(state));
-
-    let (mut trigger_save, save_trigger) = mpsc::channel(0);
-    let state_save = state.clone();
-    let save_log = logger.clone();
-    let save_context = params.uuid.clone();
-    let save = async {
-        let mut triggers = tokio::time::throttle(Duration::from_secs(5), save_trigger);
-        while let Some(_) = triggers.next().await {
-            debug!(save_log, "saving…");
-            let settings = { settings::prepare_for_save(&state_save.lock().unwrap().settings) };
-            match out_sink
-                .send(MessageOut::SetGlobalSettings {
-                    context: save_context.to_string(),
-                    payload: settings,
-                })
-                .await
-            {
-                Ok(_) => debug!(save_log, "settings saved"),
-                Err(error) => error!(save_log, "settings could not be saved: {:?}", error),
+        Err(error) => report_error(&logger, &mut state, error, None).await,
+    }
+
+    let state = run_state(logger.clone(), state, command_stream);
+
+    let save = save::run(
+        logger.clone(),
+        commands.clone(),
+        params.uuid.clone(),
+        out_sink.clone(),
+        save_requests,
+    );
+
+    let watch_commands = commands.clone();
+    let events = async {
+        while let Some(evt) = events.next().await {
+            if watch_commands.unbounded_send(Command::Watch(evt)).is_err() {
+                break;
             }
         }
     };
 
-    let state_events = state.clone();
-    let logger_events = logger.clone();
-    let mut trigger_save_events = trigger_save.clone();
-    let events = async {
-        let mut events = sb::watch(&logger).await.unwrap();
-        while let Some(evt) = events.next().await {
-            let evt = evt.unwrap();
-            debug!(logger_events, "saw change: {:?}", evt);
-            let mut state = state_events.lock().unwrap();
-            match evt {
-                ChangeEvent::SoundCore(ref evt)
-                    if evt.feature == "Device Control" && evt.parameter == "SelectOutput" =>
-                {
-                    match Output::try_from(&evt.value) {
-                        Some(output) => {
-                            state.output = Some(output);
-                            let RawState {
-                                ref mut out,
-                                ref contexts,
-                                ..
-                            } = *state;
-                            for context in contexts.iter() {
-                                out.send(MessageOut::SetState {
-                                    context: context.to_owned(),
-                                    payload: StatePayload {
-                                        state: output.into(),
-                                    },
-                                })
-                                .await
-                                .expect("failed to queue message");
-                            }
-                        }
-                        None => {
-                            warn!(
-                                logger_events,
-                                "output device changed to unrecognized value {:?}", evt.value
-                            );
-                            state.output = None;
-                        }
-                    }
+    let default_commands = commands.clone();
+    let default_endpoint = async {
+        let mut last = None;
+        while !default_commands.is_closed() {
+            if !*keys_shown_rx.borrow() {
+                // with no keys, it does not matter which device is the default
+                match keys_shown_rx.recv().await {
+                    Some(_) => continue,
+                    None => return,
                 }
-                ChangeEvent::SoundCore(evt) => {
-                    if let Some(output) = state.output {
-                        // Why update the profile here if we update the profile again right
-                        // before switching? If the user changes a setting and then
-                        // manually switches outputs, we want to capture that setting for
-                        // the next time the user switches back to the original output.
-                        let feature = state.settings.profiles[output]
-                            .parameters
-                            .entry(evt.feature)
-                            .or_default();
-                        feature.insert(evt.parameter, evt.value);
-                    }
-                }
-                ChangeEvent::Volume(volume) => {
-                    if let Some(output) = state.output {
-                        state.settings.profiles[output].volume = Some(volume);
+            }
+            match device.default_endpoint_id().await {
+                // the devices are only listed for a name when the default changes
+                Ok(id) if last.as_ref() != Some(&id) => match device.endpoint(id.clone()).await {
+                    Ok(endpoint) => {
+                        last = Some(id);
+                        let _ = default_commands.unbounded_send(Command::DefaultEndpoint(endpoint));
                     }
-                }
+                    Err(error) => debug!(
+                        logger,
+                        "could not look up the default playback device: {}", error
+                    ),
+                },
+                Ok(_) => {}
+                Err(error) => debug!(
+                    logger,
+                    "could not find the default playback device: {}", error
+                ),
             }
-            let _ = trigger_save_events.try_send(());
+            time::delay_for(DEFAULT_POLL_INTERVAL).await;
         }
     };
 
-    let get_settings_context = params.uuid.clone();
-    let test = async {
-        let (mut sink, mut stream) = connect(params).await.expect("connection failed").split();
-
-        let send_task = async {
-            sink.send(MessageOut::GetGlobalSettings {
-                context: get_settings_context,
-            })
-            .await
-            .expect("failed to send message");
-            while let Some(message) = out_stream.next().await {
-                sink.send(message).await.expect("failed to send message");
-            }
-        };
+    let (closed, closed_stream) = oneshot::channel();
+    let (stop, stop_stream) = oneshot::channel();
+    let connection = connection::run(
+        logger.clone(),
+        params,
+        out_stream,
+        commands.clone(),
+        closed,
+        stop_stream,
+    );
 
-        let receive_task = async {
-            let logger_e = logger.clone();
-            while let Some(message) = stream.next().await {
-                match message {
-                    Err(e) => crit!(logger_e, "receive failed {:?}", e),
-                    Ok(message) => {
-                        debug!(logger, "received {:?}", message);
-                        handle_message(&logger, message, &state, &mut trigger_save).await;
-                    }
-                }
+    let shutdown = async {
+        let interrupted = async {
+            if tokio::signal::ctrl_c().await.is_err() {
+                future::pending::<()>().await;
             }
         };
+        // the Stream Deck software closing the connection is a shutdown too
+        future::select(Box::pin(interrupted), closed_stream).await;
+        info!(logger, "shutting down");
+        // the connection is still open for the settings to be sent
+        saver.flush().await;
+        let (stopped, stopped_stream) = oneshot::channel();
+        if commands.unbounded_send(Command::Stop(stopped)).is_ok() {
+            let _ = stopped_stream.await;
+        }
+        // the connection finishes once everything already queued has been sent
+        let _ = stop.send(());
+    };
 
-        futures::join!(send_task, receive_task);
+    let background = async {
+        futures::join!(state, save, events, default_endpoint, log_task);
+    };
+    let foreground = async {
+        futures::join!(connection, shutdown);
     };
+    future::select(Box::pin(background), Box::pin(foreground)).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    futures::join!(save, events, log_task, test);
+    fn turn(context: &str, ticks: i32) -> DialMessage {
+        DialMessage::DialRotate {
+            context: context.to_owned(),
+            payload: connection::DialRotatePayload { ticks },
+        }
+    }
+
+    fn ticks(message: &DialMessage) -> Option<i32> {
+        match message {
+            DialMessage::DialRotate { payload, .. } => Some(payload.ticks),
+            _ => None,
+        }
+    }
+
+    fn range(kind: ParameterKind, min: Option<f64>, max: Option<f64>) -> ParameterInfo {
+        ParameterInfo {
+            kind,
+            min,
+            max,
+            step: None,
+        }
+    }
+
+    /// A parameter value as its kind and number, since `SoundCoreParamValue`
+    /// cannot be compared.
+    fn plain(value: &SoundCoreParamValue) -> (ParameterKind, f64) {
+        match *value {
+            SoundCoreParamValue::Float(n) => (ParameterKind::Float, f64::from(n)),
+            SoundCoreParamValue::I32(n) => (ParameterKind::I32, f64::from(n)),
+            SoundCoreParamValue::U32(n) => (ParameterKind::U32, f64::from(n)),
+            SoundCoreParamValue::Bool(b) => (ParameterKind::Bool, if b { 1.0 } else { 0.0 }),
+            _ => panic!("not a parameter value"),
+        }
+    }
+
+    #[test]
+    fn stepped_moves_within_the_range() {
+        let info = range(ParameterKind::Float, Some(-12.0), Some(12.0));
+        assert_eq!(
+            plain(&stepped(&SoundCoreParamValue::Float(1.5), &info, 2.0)),
+            (ParameterKind::Float, 3.5)
+        );
+        assert_eq!(
+            plain(&stepped(&SoundCoreParamValue::Float(11.0), &info, 2.0)),
+            (ParameterKind::Float, 12.0)
+        );
+        assert_eq!(
+            plain(&stepped(&SoundCoreParamValue::Float(-11.0), &info, -2.0)),
+            (ParameterKind::Float, -12.0)
+        );
+    }
+
+    #[test]
+    fn stepped_rounds_whole_numbers() {
+        let info = range(ParameterKind::U32, Some(50.0), Some(250.0));
+        assert_eq!(
+            plain(&stepped(&SoundCoreParamValue::U32(100), &info, 9.6)),
+            (ParameterKind::U32, 110.0)
+        );
+        assert_eq!(
+            plain(&stepped(&SoundCoreParamValue::U32(60), &info, -20.0)),
+            (ParameterKind::U32, 50.0)
+        );
+        let info = range(ParameterKind::I32, Some(-5.0), Some(5.0));
+        assert_eq!(
+            plain(&stepped(&SoundCoreParamValue::I32(-4), &info, -1.4)),
+            (ParameterKind::I32, -5.0)
+        );
+    }
+
+    #[test]
+    fn stepped_is_unbounded_without_a_range() {
+        let info = range(ParameterKind::Float, None, None);
+        assert_eq!(
+            plain(&stepped(&SoundCoreParamValue::Float(100.0), &info, 50.0)),
+            (ParameterKind::Float, 150.0)
+        );
+        let info = range(ParameterKind::I32, Some(0.0), None);
+        assert_eq!(
+            plain(&stepped(&SoundCoreParamValue::I32(3), &info, -5.0)),
+            (ParameterKind::I32, 0.0)
+        );
+    }
+
+    #[test]
+    fn stepped_leaves_switches_alone() {
+        let info = range(ParameterKind::Bool, None, None);
+        assert_eq!(
+            plain(&stepped(&SoundCoreParamValue::Bool(true), &info, 1.0)),
+            (ParameterKind::Bool, 1.0)
+        );
+    }
+
+    #[test]
+    fn parameter_value_takes_the_parameter_kind() {
+        let info = range(ParameterKind::Float, Some(0.0), Some(1.0));
+        assert_eq!(
+            parameter_value(&info, &serde_json::json!(0.5))
+                .as_ref()
+                .map(plain),
+            Some((ParameterKind::Float, 0.5))
+        );
+        assert_eq!(
+            parameter_value(&info, &serde_json::json!(1))
+                .as_ref()
+                .map(plain),
+            Some((ParameterKind::Float, 1.0))
+        );
+        let info = range(ParameterKind::U32, Some(0.0), Some(2.0));
+        assert_eq!(
+            parameter_value(&info, &serde_json::json!(2))
+                .as_ref()
+                .map(plain),
+            Some((ParameterKind::U32, 2.0))
+        );
+        let info = range(ParameterKind::I32, Some(-5.0), Some(5.0));
+        assert_eq!(
+            parameter_value(&info, &serde_json::json!(-5))
+                .as_ref()
+                .map(plain),
+            Some((ParameterKind::I32, -5.0))
+        );
+        let info = range(ParameterKind::Bool, None, None);
+        assert_eq!(
+            parameter_value(&info, &serde_json::json!(true))
+                .as_ref()
+                .map(plain),
+            Some((ParameterKind::Bool, 1.0))
+        );
+    }
+
+    #[test]
+    fn parameter_value_rejects_what_the_card_does_not_accept() {
+        let info = range(ParameterKind::U32, Some(0.0), Some(2.0));
+        assert!(parameter_value(&info, &serde_json::json!(3)).is_none());
+        assert!(parameter_value(&info, &serde_json::json!(1.5)).is_none());
+        assert!(parameter_value(&info, &serde_json::json!(true)).is_none());
+        let info = range(ParameterKind::Bool, None, None);
+        assert!(parameter_value(&info, &serde_json::json!(1)).is_none());
+        let info = range(ParameterKind::U32, None, None);
+        assert!(parameter_value(&info, &serde_json::json!(-1)).is_none());
+    }
+
+    fn cycle(values: &[u32]) -> Vec<CycleValue> {
+        values
+            .iter()
+            .map(|&value| CycleValue {
+                value,
+                label: value.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn next_in_cycle_moves_to_the_next_value() {
+        let values = cycle(&[0, 2, 1]);
+        assert_eq!(next_in_cycle(&values, 0), Some(2));
+        assert_eq!(next_in_cycle(&values, 2), Some(1));
+    }
+
+    #[test]
+    fn next_in_cycle_wraps_around() {
+        assert_eq!(next_in_cycle(&cycle(&[0, 2, 1]), 1), Some(0));
+        assert_eq!(next_in_cycle(&cycle(&[4]), 4), Some(4));
+    }
+
+    #[test]
+    fn next_in_cycle_starts_over_from_an_unlisted_value() {
+        assert_eq!(next_in_cycle(&cycle(&[3, 5]), 7), Some(3));
+    }
+
+    #[test]
+    fn next_in_cycle_has_nothing_for_an_empty_list() {
+        assert_eq!(next_in_cycle(&[], 0), None);
+    }
+
+    #[test]
+    fn merge_dial_turns_adds_up_queued_turns() {
+        let (commands, mut queue) = mpsc::unbounded();
+        commands
+            .unbounded_send(Command::Dial(turn("a", 2)))
+            .unwrap();
+        commands
+            .unbounded_send(Command::Dial(turn("a", -1)))
+            .unwrap();
+        commands
+            .unbounded_send(Command::Dial(turn("b", 5)))
+            .unwrap();
+        commands
+            .unbounded_send(Command::Dial(turn("a", 3)))
+            .unwrap();
+        let (message, next) = merge_dial_turns(turn("a", 1), &mut queue);
+        assert_eq!(ticks(&message), Some(2));
+        match next {
+            Some(Command::Dial(message)) => assert_eq!(ticks(&message), Some(5)),
+            _ => panic!("expected the other dial's turn"),
+        }
+        match queue.try_next() {
+            Ok(Some(Command::Dial(message))) => assert_eq!(ticks(&message), Some(3)),
+            _ => panic!("expected the turn after the other dial's"),
+        }
+    }
+
+    #[test]
+    fn merge_dial_turns_leaves_presses_alone() {
+        let (commands, mut queue) = mpsc::unbounded();
+        commands
+            .unbounded_send(Command::Dial(turn("a", 2)))
+            .unwrap();
+        let press = DialMessage::DialDown {
+            context: "a".to_owned(),
+        };
+        let (message, next) = merge_dial_turns(press, &mut queue);
+        assert_eq!(ticks(&message), None);
+        assert!(next.is_none());
+    }
 }
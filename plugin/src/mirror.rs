@@ -0,0 +1,87 @@
+use crate::sb::{self, ChangeEvent};
+use crate::types::*;
+use indexmap::{IndexMap, IndexSet};
+use sbz_switch::soundcore::SoundCoreParamValue;
+use sbz_switch::Configuration;
+
+pub type Features = IndexMap<String, IndexMap<String, SoundCoreParamValue>>;
+
+/// What the sound card is set to, as of the last dump plus every change seen since.
+///
+/// This saves dumping the whole card for every key press and every time the
+/// property inspector opens. It can only be trusted while the watcher is
+/// running, and should be thrown away whenever talking to the card fails.
+#[derive(Debug)]
+pub struct Mirror {
+    current: Profile,
+    muted: Option<bool>,
+}
+
+impl Mirror {
+    pub fn new(configuration: Configuration, muted: Option<bool>) -> Self {
+        Self {
+            muted,
+            current: Profile {
+                volume: configuration
+                    .endpoint
+                    .as_ref()
+                    .and_then(|endpoint| endpoint.volume),
+                parameters: configuration.creative.unwrap_or_default(),
+            },
+        }
+    }
+
+    /// Everything the card is set to.
+    pub fn current(&self) -> &Profile {
+        &self.current
+    }
+
+    /// Whether the card is muted, if that could be read.
+    pub fn muted(&self) -> Option<bool> {
+        self.muted
+    }
+
+    /// Every SoundCore parameter and its value.
+    pub fn features(&self) -> &Features {
+        &self.current.parameters
+    }
+
+    pub fn output(&self) -> Option<Output> {
+        self.current
+            .parameters
+            .get("Device Control")
+            .and_then(|control| control.get("SelectOutput"))
+            .and_then(Output::try_from)
+    }
+
+    /// The active output and everything that would be saved for it.
+    pub fn profile(&self) -> Option<(Output, Profile)> {
+        self.output().map(|output| (output, self.current.clone()))
+    }
+
+    pub fn record(&mut self, event: ChangeEvent) {
+        if let ChangeEvent::Mute(muted) = event {
+            self.muted = Some(muted);
+        }
+        self.current.record(event);
+    }
+
+    /// Update the mirror after a profile has been applied successfully.
+    pub fn applied(
+        &mut self,
+        output: Output,
+        profile: &Profile,
+        selected_parameters: &IndexMap<String, IndexSet<String>>,
+    ) {
+        for (name, params) in sb::profile_parameters(output, profile, selected_parameters) {
+            self.current
+                .parameters
+                .entry(name)
+                .or_default()
+                .extend(params);
+        }
+        if profile.volume.is_some() {
+            self.current.volume = profile.volume;
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use crate::connection::OutMessage;
+use crate::settings;
+use crate::types::*;
+use futures::channel::{mpsc, oneshot};
+use futures::prelude::*;
+use slog::{debug, error, warn, Logger};
+use std::time::Duration;
+use streamdeck_rs::MessageOut;
+use tokio::time::{self, Instant};
+
+#[derive(Debug)]
+enum SaveRequest {
+    /// Save once there have been no changes for the given time.
+    Later(Duration),
+    /// Save right away.
+    Now,
+    /// Save right away if a save is pending, and report back once it has been queued.
+    Flush(oneshot::Sender<()>),
+}
+
+/// A handle for asking the settings to be saved.
+#[derive(Clone)]
+pub struct Saver {
+    requests: mpsc::UnboundedSender<SaveRequest>,
+}
+
+impl Saver {
+    /// Save after the settings have stopped changing for `delay`.
+    pub fn later(&self, delay: Duration) {
+        let _ = self.requests.unbounded_send(SaveRequest::Later(delay));
+    }
+
+    /// Save without waiting.
+    pub fn now(&self) {
+        let _ = self.requests.unbounded_send(SaveRequest::Now);
+    }
+
+    /// Save immediately if there are unsaved changes.
+    ///
+    /// This must not be awaited by the task that owns the state, which has to
+    /// answer the saver's request for the settings.
+    pub async fn flush(&self) {
+        let (done_tx, done_rx) = oneshot::channel();
+        if self
+            .requests
+            .unbounded_send(SaveRequest::Flush(done_tx))
+            .is_ok()
+        {
+            let _ = done_rx.await;
+        }
+    }
+}
+
+pub struct SaveRequests(mpsc::UnboundedReceiver<SaveRequest>);
+
+pub fn channel() -> (Saver, SaveRequests) {
+    let (requests, receiver) = mpsc::unbounded();
+    (Saver { requests }, SaveRequests(receiver))
+}
+
+/// The settings as they should be stored, if it is safe to store them.
+pub fn snapshot(logger: &Logger, state: &RawState) -> Option<serde_json::Value> {
+    if !state.settings_loaded {
+        // saving now would replace the stored profiles with the few things seen so far
+        debug!(logger, "not saving until settings have been loaded");
+        return None;
+    }
+    if state.quarantine.is_some() {
+        warn!(logger, "not saving over settings that could not be loaded");
+        return None;
+    }
+    Some(
+        serde_json::to_value(settings::prepare_for_save(&state.settings))
+            .expect("failed to serialize settings"),
+    )
+}
+
+async fn save(
+    logger: &Logger,
+    commands: &mpsc::UnboundedSender<Command>,
+    context: &str,
+    out: &mpsc::UnboundedSender<OutMessage>,
+) {
+    let (reply, settings) = oneshot::channel();
+    if commands.unbounded_send(Command::Snapshot(reply)).is_err() {
+        return;
+    }
+    let settings = match settings.await {
+        Ok(Some(settings)) => settings,
+        _ => return,
+    };
+    debug!(logger, "saving…");
+    match out.unbounded_send(OutMessage::Sdk(MessageOut::SetGlobalSettings {
+        context: context.to_string(),
+        payload: settings,
+    })) {
+        Ok(_) => debug!(logger, "settings saved"),
+        Err(error) => error!(logger, "settings could not be saved: {:?}", error),
+    }
+}
+
+/// Save the settings whenever requested.
+///
+/// Requests to save later are debounced so that a burst of changes results in
+/// a single save once the changes stop.
+pub async fn run(
+    logger: Logger,
+    commands: mpsc::UnboundedSender<Command>,
+    context: String,
+    out: mpsc::UnboundedSender<OutMessage>,
+    requests: SaveRequests,
+) {
+    let SaveRequests(mut requests) = requests;
+    let mut pending: Option<Instant> = None;
+    loop {
+        let request = match pending {
+            Some(deadline) => match time::timeout_at(deadline, requests.next()).await {
+                Ok(request) => request,
+                Err(_) => {
+                    pending = None;
+                    save(&logger, &commands, &context, &out).await;
+                    continue;
+                }
+            },
+            None => requests.next().await,
+        };
+        match request {
+            Some(SaveRequest::Later(delay)) => {
+                pending = Some(Instant::now() + delay);
+            }
+            Some(SaveRequest::Now) => {
+                pending = None;
+                save(&logger, &commands, &context, &out).await;
+            }
+            Some(SaveRequest::Flush(done)) => {
+                if pending.take().is_some() {
+                    save(&logger, &commands, &context, &out).await;
+                }
+                let _ = done.send(());
+            }
+            None => {
+                if pending.is_some() {
+                    save(&logger, &commands, &context, &out).await;
+                }
+                return;
+            }
+        }
+    }
+}
@@ -1,50 +1,25 @@
 use crate::types::*;
-use futures::channel::{mpsc, oneshot};
+use futures::channel::mpsc;
 use futures::executor;
 use futures::prelude::*;
 use indexmap::{IndexMap, IndexSet};
-use sbz_switch::media::VolumeNotification;
-use sbz_switch::soundcore::{SoundCoreEvent, SoundCoreParamValue};
-use sbz_switch::{Configuration, EndpointConfiguration, SoundCoreOrVolumeEvent, Win32Error};
-use slog::error;
-use slog::Logger;
-use std::{iter, thread};
-
-pub fn get_current_profile(
-    logger: &Logger,
-) -> Result<Option<(Output, Profile)>, Box<dyn std::error::Error>> {
-    match sbz_switch::dump(logger, None) {
-        Ok(device_state) => Ok(match device_state.creative {
-            Some(creative) => {
-                let output = creative
-                    .get("Device Control")
-                    .and_then(|control| control.get("SelectOutput").and_then(Output::try_from));
-                match output {
-                    Some(output) => Some((
-                        output,
-                        Profile {
-                            volume: device_state
-                                .endpoint
-                                .as_ref()
-                                .and_then(|endpoint| endpoint.volume),
-                            parameters: creative,
-                        },
-                    )),
-                    None => None,
-                }
-            }
-            None => None,
-        }),
-        Err(error) => Err(error),
-    }
-}
+use sbz_switch::media::{DeviceEnumerator, VolumeNotification};
+use sbz_switch::soundcore::{SoundCore, SoundCoreEvent, SoundCoreParamValue};
+use sbz_switch::{Configuration, EndpointConfiguration, SoundCoreOrVolumeEvent};
+use slog::{error, info, warn, Logger};
+use std::ffi::OsStr;
+use std::sync::mpsc::{self as std_mpsc, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::{cmp, iter, thread};
+use tokio::time::{self, Instant};
 
-pub fn apply_profile(
-    logger: &Logger,
+/// The SoundCore parameters to set when switching to `output`.
+pub fn profile_parameters(
     output: Output,
     profile: &Profile,
     selected_parameters: &IndexMap<String, IndexSet<String>>,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> IndexMap<String, IndexMap<String, SoundCoreParamValue>> {
     let mut creative: IndexMap<String, IndexMap<String, SoundCoreParamValue>> = iter::once((
         "Device Control".to_owned(),
         iter::once((
@@ -65,72 +40,371 @@ pub fn apply_profile(
             }
         }
     }
+    creative
+}
 
+/// Switch `card`, or the default playback device if there is none, to
+/// `output` and apply the selected parameters from `profile`.
+pub fn apply_profile(
+    logger: &Logger,
+    card: Option<&str>,
+    output: Output,
+    profile: &Profile,
+    selected_parameters: &IndexMap<String, IndexSet<String>>,
+) -> Result<(), Box<dyn std::error::Error>> {
     let configuration = Configuration {
         endpoint: Some(EndpointConfiguration {
             volume: profile.volume,
         }),
-        creative: Some(creative),
+        creative: Some(profile_parameters(output, profile, selected_parameters)),
     };
-    sbz_switch::set(&logger, None, &configuration, true)
+    sbz_switch::set(&logger, card.map(OsStr::new), &configuration, true)
 }
 
-#[derive(Debug)]
+/// The endpoint for `card`, or the default playback device if there is none.
+fn card_endpoint(
+    logger: &Logger,
+    card: Option<&str>,
+) -> Result<sbz_switch::media::Endpoint, Box<dyn std::error::Error>> {
+    let enumerator = DeviceEnumerator::with_logger(logger.clone())?;
+    Ok(match card {
+        Some(id) => enumerator.get_endpoint(OsStr::new(id))?,
+        None => enumerator.get_default_audio_endpoint()?,
+    })
+}
+
+/// Whether `card`, or the default playback device if there is none, is muted.
+pub fn is_muted(logger: &Logger, card: Option<&str>) -> Result<bool, Box<dyn std::error::Error>> {
+    Ok(card_endpoint(logger, card)?.get_mute()?)
+}
+
+/// Make one change to `card`, or the default playback device if there is
+/// none, without muting around it.
+pub fn apply_change(
+    logger: &Logger,
+    card: Option<&str>,
+    change: &ChangeEvent,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let configuration = match change {
+        ChangeEvent::Mute(muted) => {
+            card_endpoint(logger, card)?.set_mute(*muted)?;
+            return Ok(());
+        }
+        ChangeEvent::Volume(volume) => Configuration {
+            endpoint: Some(EndpointConfiguration {
+                volume: Some(*volume),
+            }),
+            creative: None,
+        },
+        ChangeEvent::SoundCore(event) => Configuration {
+            endpoint: None,
+            creative: Some(
+                iter::once((
+                    event.feature.clone(),
+                    iter::once((event.parameter.clone(), event.value.clone())).collect(),
+                ))
+                .collect(),
+            ),
+        },
+    };
+    sbz_switch::set(logger, card.map(OsStr::new), &configuration, false)
+}
+
+fn as_f64(value: &SoundCoreParamValue) -> Option<f64> {
+    match *value {
+        SoundCoreParamValue::I32(n) => Some(f64::from(n)),
+        SoundCoreParamValue::U32(n) => Some(f64::from(n)),
+        SoundCoreParamValue::Float(n) => Some(f64::from(n)),
+        _ => None,
+    }
+}
+
+/// Find out what every SoundCore parameter of `card`, or the default
+/// playback device if there is none, accepts.
+pub fn catalog(logger: &Logger, card: Option<&str>) -> Result<Catalog, Box<dyn std::error::Error>> {
+    let endpoint = card_endpoint(logger, card)?;
+    let core = SoundCore::for_device(&endpoint.clsid()?, &endpoint.id()?, logger.clone())?;
+    let mut catalog = Catalog::new();
+    for feature in core.features(0) {
+        let feature = feature?;
+        for parameter in feature.parameters() {
+            let parameter = parameter?;
+            // many parameters have no range, so the current value is the
+            // surest sign of what they hold
+            let kind = match parameter.get() {
+                Ok(value) => kind_of(&value),
+                Err(_) => kind_of(&parameter.min_value),
+            };
+            let kind = match kind {
+                Some(kind) => kind,
+                None => continue,
+            };
+            let info = match kind {
+                ParameterKind::Bool => ParameterInfo {
+                    kind,
+                    min: None,
+                    max: None,
+                    step: None,
+                },
+                _ => ParameterInfo {
+                    kind,
+                    min: as_f64(&parameter.min_value),
+                    max: as_f64(&parameter.max_value),
+                    step: as_f64(&parameter.step_size),
+                },
+            };
+            catalog
+                .entry(feature.description.clone())
+                .or_default()
+                .insert(parameter.description.clone(), info);
+        }
+    }
+    Ok(catalog)
+}
+
+fn kind_of(value: &SoundCoreParamValue) -> Option<ParameterKind> {
+    match value {
+        SoundCoreParamValue::Float(_) => Some(ParameterKind::Float),
+        SoundCoreParamValue::I32(_) => Some(ParameterKind::I32),
+        SoundCoreParamValue::U32(_) => Some(ParameterKind::U32),
+        SoundCoreParamValue::Bool(_) => Some(ParameterKind::Bool),
+        _ => None,
+    }
+}
+
+/// A playback device known to Windows.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Endpoint {
+    pub id: String,
+    pub name: String,
+}
+
+/// Find out which playback device is the default.
+///
+/// The plugin only works with the sound card while it is the default, so
+/// this is what decides whether it is paused.
+pub fn default_endpoint_id(logger: &Logger) -> Result<String, Box<dyn std::error::Error>> {
+    Ok(DeviceEnumerator::with_logger(logger.clone())?
+        .get_default_audio_endpoint()?
+        .id()?)
+}
+
+/// Look up the name of a playback device, which is its ID if it is not listed.
+pub fn endpoint(logger: &Logger, id: &str) -> Result<Endpoint, Box<dyn std::error::Error>> {
+    let name = endpoints(logger)?
+        .into_iter()
+        .find(|endpoint| endpoint.id == id)
+        .map(|endpoint| endpoint.name)
+        .unwrap_or_else(|| id.to_owned());
+    Ok(Endpoint {
+        id: id.to_owned(),
+        name,
+    })
+}
+
+/// List the playback devices.
+pub fn endpoints(logger: &Logger) -> Result<Vec<Endpoint>, Box<dyn std::error::Error>> {
+    Ok(sbz_switch::list_devices(logger)?
+        .into_iter()
+        .map(|device| Endpoint {
+            id: device.id,
+            name: device.description,
+        })
+        .collect())
+}
+
+#[derive(Clone, Debug)]
 pub enum ChangeEvent {
     SoundCore(SoundCoreChangeEvent),
     Volume(f32),
+    Mute(bool),
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct SoundCoreChangeEvent {
     pub feature: String,
     pub parameter: String,
     pub value: SoundCoreParamValue,
 }
 
-pub async fn watch(logger: &Logger) -> Result<mpsc::Receiver<Result<ChangeEvent, Win32Error>>, ()> {
-    let (start_tx, start_rx) = oneshot::channel();
+#[derive(Debug)]
+pub enum WatchEvent {
+    /// The watcher has (re)started and changes will be reported.
+    Started,
+    /// The watcher has stopped and changes may be missed until it restarts.
+    Failed,
+    /// Changes seen close together, oldest first.
+    Changes(Vec<ChangeEvent>),
+}
+
+/// How long to keep collecting after the first change of a burst.
+const BATCH_WINDOW: Duration = Duration::from_millis(100);
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+enum Stopped {
+    Failed { started: bool },
+    Closed,
+}
+
+fn watch_until_stopped(
+    logger: &Logger,
+    card: Option<&str>,
+    event_tx: &mut mpsc::Sender<WatchEvent>,
+) -> Stopped {
+    let iterator = match sbz_switch::watch_with_volume(logger, card.map(OsStr::new)) {
+        Ok(iterator) => iterator,
+        Err(error) => {
+            error!(logger, "failed to listen for events: {:?}", error);
+            return Stopped::Failed { started: false };
+        }
+    };
+    if executor::block_on(event_tx.send(WatchEvent::Started)).is_err() {
+        return Stopped::Closed;
+    }
+    for event in iterator {
+        let events = match event {
+            Ok(SoundCoreOrVolumeEvent::SoundCore(SoundCoreEvent::ParamChange {
+                feature,
+                parameter,
+            })) => match parameter.get() {
+                Ok(value) => vec![ChangeEvent::SoundCore(SoundCoreChangeEvent {
+                    feature: feature.description.to_owned(),
+                    parameter: parameter.description.to_owned(),
+                    value,
+                })],
+                Err(error) => {
+                    warn!(
+                        logger,
+                        "failed to read {}/{}: {:?}",
+                        feature.description,
+                        parameter.description,
+                        error
+                    );
+                    continue;
+                }
+            },
+            // Windows keeps the volume while muted, and it can still be changed
+            Ok(SoundCoreOrVolumeEvent::Volume(VolumeNotification {
+                volume, is_muted, ..
+            })) => vec![ChangeEvent::Volume(volume), ChangeEvent::Mute(is_muted)],
+            Ok(_) => continue,
+            Err(error) => {
+                error!(logger, "event watcher failed: {:?}", error);
+                return Stopped::Failed { started: true };
+            }
+        };
+        if executor::block_on(event_tx.send(WatchEvent::Changes(events))).is_err() {
+            return Stopped::Closed;
+        }
+    }
+    warn!(logger, "event watcher stopped");
+    Stopped::Failed { started: true }
+}
+
+/// Events from the sound card, with bursts of changes grouped together.
+///
+/// Dragging the volume slider or loading a preset produces a flood of
+/// changes, which are much cheaper to handle and save all at once.
+pub struct Watcher {
+    events: mpsc::Receiver<WatchEvent>,
+    held: Option<WatchEvent>,
+}
+
+impl Watcher {
+    pub async fn next(&mut self) -> Option<WatchEvent> {
+        let mut event = match self.held.take() {
+            Some(event) => event,
+            None => self.events.next().await?,
+        };
+        if let WatchEvent::Changes(ref mut changes) = event {
+            let deadline = Instant::now() + BATCH_WINDOW;
+            loop {
+                match time::timeout_at(deadline, self.events.next()).await {
+                    Ok(Some(WatchEvent::Changes(more))) => changes.extend(more),
+                    Ok(Some(other)) => {
+                        // handled after the changes that came before it
+                        self.held = Some(other);
+                        break;
+                    }
+                    Ok(None) | Err(_) => break,
+                }
+            }
+        }
+        Some(event)
+    }
+}
+
+/// A handle for restarting a failed watcher early.
+pub struct Reattach {
+    requests: std_mpsc::Sender<()>,
+    card: Arc<Mutex<Option<String>>>,
+}
+
+impl Reattach {
+    /// Try again now instead of waiting out the restart delay, for example
+    /// because the sound card has come back.
+    pub fn now(&self) {
+        let _ = self.requests.send(());
+    }
+
+    /// Watch `card` instead of the default playback device from the next
+    /// start on.
+    pub fn set_card(&self, card: Option<String>) {
+        *self.card.lock().unwrap() = card;
+    }
+}
+
+/// Watch the sound card for changes.
+///
+/// The watcher runs on its own thread and is restarted with an increasing
+/// delay whenever it fails. It stops when the `Watcher` is dropped.
+pub fn watch(logger: &Logger) -> (Watcher, Reattach) {
+    let (mut event_tx, event_rx) = mpsc::channel(64);
+    let (reattach_tx, reattach_rx) = std_mpsc::channel();
+    let card = Arc::new(Mutex::new(None));
+    let reattach = Reattach {
+        requests: reattach_tx,
+        card: card.clone(),
+    };
     let logger = logger.clone();
     thread::Builder::new()
         .name("event thread".into())
-        .spawn(move || match sbz_switch::watch_with_volume(&logger, None) {
-            Ok(iterator) => {
-                let (mut event_tx, event_rx) = mpsc::channel(64);
-                start_tx.send(Ok(event_rx)).unwrap();
-                for event in iterator {
-                    match event {
-                        Ok(SoundCoreOrVolumeEvent::SoundCore(SoundCoreEvent::ParamChange {
-                            feature,
-                            parameter,
-                        })) => {
-                            let event = match parameter.get() {
-                                Ok(value) => Ok(ChangeEvent::SoundCore(SoundCoreChangeEvent {
-                                    feature: feature.description.to_owned(),
-                                    parameter: parameter.description.to_owned(),
-                                    value,
-                                })),
-                                Err(error) => Err(error),
-                            };
-                            executor::block_on(event_tx.send(event)).unwrap();
+        .spawn(move || {
+            let mut delay = MIN_RESTART_DELAY;
+            loop {
+                let card = card.lock().unwrap().clone();
+                let card = card.as_ref().map(String::as_str);
+                match watch_until_stopped(&logger, card, &mut event_tx) {
+                    Stopped::Closed => return,
+                    Stopped::Failed { started } => {
+                        if started {
+                            delay = MIN_RESTART_DELAY;
+                        }
+                        if executor::block_on(event_tx.send(WatchEvent::Failed)).is_err() {
+                            return;
                         }
-                        Ok(SoundCoreOrVolumeEvent::Volume(VolumeNotification {
-                            volume,
-                            is_muted,
-                            ..
-                        })) if !is_muted => {
-                            executor::block_on(event_tx.send(Ok(ChangeEvent::Volume(volume))))
-                                .unwrap()
+                        // only requests made while waiting count
+                        while reattach_rx.try_recv().is_ok() {}
+                        info!(logger, "restarting event watcher in {:?}", delay);
+                        match reattach_rx.recv_timeout(delay) {
+                            Ok(()) => {
+                                info!(logger, "restarting event watcher early");
+                                delay = MIN_RESTART_DELAY;
+                                continue;
+                            }
+                            Err(RecvTimeoutError::Timeout) => {}
+                            Err(RecvTimeoutError::Disconnected) => thread::sleep(delay),
                         }
-                        Ok(_) => {}
-                        Err(error) => executor::block_on(event_tx.send(Err(error))).unwrap(),
+                        delay = cmp::min(delay * 2, MAX_RESTART_DELAY);
                     }
                 }
             }
-            Err(error) => {
-                error!(logger, "failed to listen for events: {:?}", error);
-                start_tx.send(Err(())).unwrap();
-            }
         })
-        .unwrap();
-    start_rx.await.unwrap()
+        .expect("failed to start event thread");
+    let watcher = Watcher {
+        events: event_rx,
+        held: None,
+    };
+    (watcher, reattach)
 }
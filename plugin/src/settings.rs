@@ -37,7 +37,8 @@ fn convert_to_soundcore(
         .collect()
 }
 
-pub fn load(de: SerdeCardSettings) -> Result<CardSettings, serde_json::Error> {
+pub fn load(value: serde_json::Value) -> Result<CardSettings, serde_json::Error> {
+    let de: SerdeCardSettings = serde_json::from_value(value)?;
     Ok(CardSettings {
         selected_parameters: de.selected_parameters,
         profiles: Profiles {
@@ -50,9 +51,113 @@ pub fn load(de: SerdeCardSettings) -> Result<CardSettings, serde_json::Error> {
                 parameters: convert_to_soundcore(de.profiles.speakers.parameters),
             },
         },
+        options: de.options,
+        card: de.card,
     })
 }
 
+fn salvage_profile(value: Option<&serde_json::Value>) -> Profile {
+    let volume = value
+        .and_then(|profile| profile.get("volume"))
+        .and_then(serde_json::Value::as_f64)
+        .map(|volume| volume as f32);
+    let parameters = value
+        .and_then(|profile| profile.get("parameters"))
+        .and_then(serde_json::Value::as_object)
+        .map(|features| {
+            features
+                .iter()
+                .filter_map(|(name, params)| {
+                    params.as_object().map(|params| {
+                        (
+                            name.to_owned(),
+                            params
+                                .iter()
+                                .map(|(name, value)| (name.to_owned(), value.clone()))
+                                .collect(),
+                        )
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    Profile {
+        volume,
+        parameters: convert_to_soundcore(parameters),
+    }
+}
+
+/// Recover whatever can be understood from stored settings that failed to load.
+pub fn salvage(value: &serde_json::Value) -> CardSettings {
+    let selected_parameters = value
+        .get("selected_parameters")
+        .and_then(serde_json::Value::as_object)
+        .map(|features| {
+            features
+                .iter()
+                .filter_map(|(name, params)| {
+                    params.as_array().map(|params| {
+                        (
+                            name.to_owned(),
+                            params
+                                .iter()
+                                .filter_map(serde_json::Value::as_str)
+                                .map(str::to_owned)
+                                .collect(),
+                        )
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let profiles = value.get("profiles");
+    CardSettings {
+        selected_parameters,
+        profiles: Profiles {
+            headphones: salvage_profile(profiles.and_then(|p| p.get("headphones"))),
+            speakers: salvage_profile(profiles.and_then(|p| p.get("speakers"))),
+        },
+        options: value
+            .get("options")
+            .and_then(|options| serde_json::from_value(options.clone()).ok())
+            .unwrap_or_default(),
+        card: value
+            .get("card")
+            .and_then(serde_json::Value::as_str)
+            .map(str::to_owned),
+    }
+}
+
+fn merge_profile(stored: &mut Profile, observed: Profile) {
+    if observed.volume.is_some() {
+        stored.volume = observed.volume;
+    }
+    for (name, params) in observed.parameters {
+        stored.parameters.entry(name).or_default().extend(params);
+    }
+}
+
+/// Combine the settings stored by the Stream Deck software with what was
+/// observed on the device before they arrived.
+///
+/// The parameter selection and options always come from the stored settings,
+/// as does the sound card unless none was stored.
+/// Anything captured from the device is newer than what was stored, so
+/// observed volumes and parameter values replace the stored ones, and stored
+/// values that were not observed are kept.
+pub fn merge(mut stored: CardSettings, observed: CardSettings) -> CardSettings {
+    let Profiles {
+        headphones,
+        speakers,
+    } = observed.profiles;
+    merge_profile(&mut stored.profiles.headphones, headphones);
+    merge_profile(&mut stored.profiles.speakers, speakers);
+    if stored.card.is_none() {
+        stored.card = observed.card;
+    }
+    stored
+}
+
 fn convert_from_soundcore(
     value: &IndexMap<String, IndexMap<String, SoundCoreParamValue>>,
 ) -> BTreeMap<String, BTreeMap<String, serde_json::Value>> {
@@ -96,5 +201,7 @@ pub fn prepare_for_save(settings: &CardSettings) -> SerdeCardSettings {
                 parameters: convert_from_soundcore(&settings.profiles.speakers.parameters),
             },
         },
+        options: settings.options.clone(),
+        card: settings.card.clone(),
     }
 }
@@ -0,0 +1,136 @@
+use crate::types::*;
+use sbz_switch::soundcore::SoundCoreParamValue;
+
+/// Show a parameter value the way titles do.
+pub fn format_value(value: &SoundCoreParamValue) -> String {
+    match value {
+        SoundCoreParamValue::Bool(true) => "On".to_owned(),
+        SoundCoreParamValue::Bool(false) => "Off".to_owned(),
+        SoundCoreParamValue::I32(n) => n.to_string(),
+        SoundCoreParamValue::U32(n) => n.to_string(),
+        SoundCoreParamValue::Float(n) => format!("{:.1}", n),
+        _ => "?".to_owned(),
+    }
+}
+
+fn expand(name: &str, output: Option<Output>, profile: Option<&Profile>) -> String {
+    match name {
+        "output" => match output {
+            Some(Output::Headphones) => "Headphones".to_owned(),
+            Some(Output::Speakers) => "Speakers".to_owned(),
+            None => "?".to_owned(),
+        },
+        "out" => match output {
+            Some(Output::Headphones) => "HP".to_owned(),
+            Some(Output::Speakers) => "SPK".to_owned(),
+            None => "?".to_owned(),
+        },
+        "volume" => match profile.and_then(|profile| profile.volume) {
+            Some(volume) => format!("{:.0}", volume * 100.0),
+            None => "?".to_owned(),
+        },
+        "n" => "\n".to_owned(),
+        name => {
+            // anything else names a parameter, as in {Feature/Parameter}
+            let mut parts = name.splitn(2, '/');
+            let feature = parts.next().unwrap_or_default();
+            let parameter = parts.next().unwrap_or_default();
+            profile
+                .and_then(|profile| profile.parameters.get(feature))
+                .and_then(|feature| feature.get(parameter))
+                .map(format_value)
+                .unwrap_or_else(|| "?".to_owned())
+        }
+    }
+}
+
+/// Fill in a title template.
+///
+/// `{output}` and `{out}` are the long and short names of the output,
+/// `{volume}` is the volume in percent, `{n}` starts a new line, and
+/// `{Feature/Parameter}` is the value of any parameter. Anything that is not
+/// known shows as `?`.
+pub fn render(template: &str, output: Option<Output>, profile: Option<&Profile>) -> String {
+    let mut title = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        title.push_str(&rest[..start]);
+        match rest[start..].find('}') {
+            Some(end) => {
+                title.push_str(&expand(&rest[start + 1..start + end], output, profile));
+                rest = &rest[start + end + 1..];
+            }
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        }
+    }
+    title.push_str(rest);
+    title
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile() -> Profile {
+        let mut profile = Profile::default();
+        profile.volume = Some(0.42);
+        profile
+            .parameters
+            .entry("SBX Pro Studio".to_owned())
+            .or_default()
+            .insert("SBX".to_owned(), SoundCoreParamValue::Bool(true));
+        profile
+    }
+
+    #[test]
+    fn plain_text_is_unchanged() {
+        assert_eq!(render("Games", None, None), "Games");
+        assert_eq!(render("", None, None), "");
+    }
+
+    #[test]
+    fn fills_in_the_output_and_volume() {
+        let profile = profile();
+        assert_eq!(
+            render("{out} {volume}%", Some(Output::Headphones), Some(&profile)),
+            "HP 42%"
+        );
+        assert_eq!(
+            render(
+                "{output}{n}{volume}",
+                Some(Output::Speakers),
+                Some(&profile)
+            ),
+            "Speakers\n42"
+        );
+    }
+
+    #[test]
+    fn fills_in_parameters() {
+        let profile = profile();
+        assert_eq!(
+            render("SBX {SBX Pro Studio/SBX}", None, Some(&profile)),
+            "SBX On"
+        );
+    }
+
+    #[test]
+    fn unknown_values_show_as_question_marks() {
+        assert_eq!(render("{out} {volume}", None, None), "? ?");
+        assert_eq!(render("{Nothing/Here}", None, Some(&profile())), "?");
+        assert_eq!(render("{}", None, Some(&profile())), "?");
+        assert_eq!(render("{SBX Pro Studio}", None, Some(&profile())), "?");
+    }
+
+    #[test]
+    fn unmatched_braces_are_left_alone() {
+        let output = Some(Output::Headphones);
+        assert_eq!(render("{out", output, None), "{out");
+        assert_eq!(render("{out} {", output, None), "HP {");
+        assert_eq!(render("a } b", output, None), "a } b");
+        assert_eq!(render("}{out}{", output, None), "}HP{");
+    }
+}
@@ -1,18 +1,25 @@
-use common::SerdeCardSettings;
-pub use common::{FromInspector, ToInspector};
-use futures::channel::mpsc;
+use crate::connection::{DialMessage, InMessage, OutMessage};
+use crate::device::{Device, Retry};
+use crate::error::Error;
+use crate::image::{self, Images, Look};
+use crate::mirror::Mirror;
+use crate::save::Saver;
+use crate::sb::{ChangeEvent, Endpoint, Reattach, WatchEvent};
+pub use common::{
+    AdjustSettings, Card, Catalog, CycleSettings, CycleValue, DialPress, DialSettings, DialTarget,
+    Direction, FromInspector, Options, ParameterInfo, ParameterKind, PluginError, PluginStatus,
+    Quarantine, Recovery, ReturnPolicy, SelectOutputSettings, SetParameterSettings, ToInspector,
+};
+use futures::channel::{mpsc, oneshot};
 use indexmap::{IndexMap, IndexSet};
 use sbz_switch::soundcore::SoundCoreParamValue;
-use serde_derive::{Deserialize, Serialize};
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, VecDeque};
 use std::ops::{Index, IndexMut};
-use std::sync::{Arc, Mutex};
-use streamdeck_rs::MessageOut;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::Instant;
 
-#[derive(Debug, Deserialize, Serialize)]
-pub struct Empty {}
-
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum Output {
     Headphones,
     Speakers,
@@ -37,12 +44,29 @@ impl Output {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct Profile {
     pub volume: Option<f32>,
     pub parameters: IndexMap<String, IndexMap<String, SoundCoreParamValue>>,
 }
 
+impl Profile {
+    /// Remember a change that was made to the device.
+    pub fn record(&mut self, event: ChangeEvent) {
+        match event {
+            ChangeEvent::SoundCore(event) => {
+                self.parameters
+                    .entry(event.feature)
+                    .or_default()
+                    .insert(event.parameter, event.value);
+            }
+            ChangeEvent::Volume(volume) => self.volume = Some(volume),
+            // muting is not part of a profile
+            ChangeEvent::Mute(_) => {}
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct Profiles {
     pub headphones: Profile,
@@ -69,17 +93,325 @@ impl IndexMut<Output> for Profiles {
     }
 }
 
+/// The plugin's actions, as declared in `manifest.json`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Action {
+    SelectOutput,
+    SelectHeadphones,
+    SelectSpeakers,
+    /// A Stream Deck + dial that adjusts the volume or a parameter.
+    Dial,
+    /// Steps a numeric parameter up or down.
+    AdjustParameter,
+    /// Sets a parameter to the value stored with the key.
+    SetParameter,
+    /// Steps a `U32` parameter through a list of values, showing the label
+    /// of the current one.
+    CycleParameter,
+}
+
+impl Action {
+    pub fn from_uuid(uuid: &str) -> Option<Self> {
+        match uuid {
+            "io.github.mdonoughe.sbzdeck.selectoutput" => Some(Action::SelectOutput),
+            "io.github.mdonoughe.sbzdeck.selectheadphones" => Some(Action::SelectHeadphones),
+            "io.github.mdonoughe.sbzdeck.selectspeakers" => Some(Action::SelectSpeakers),
+            "io.github.mdonoughe.sbzdeck.dial" => Some(Action::Dial),
+            "io.github.mdonoughe.sbzdeck.adjustparameter" => Some(Action::AdjustParameter),
+            "io.github.mdonoughe.sbzdeck.setparameter" => Some(Action::SetParameter),
+            "io.github.mdonoughe.sbzdeck.cycleparameter" => Some(Action::CycleParameter),
+            _ => None,
+        }
+    }
+
+    /// The output the action always switches to, if it does not toggle.
+    pub fn fixed_output(self) -> Option<Output> {
+        match self {
+            Action::SelectOutput
+            | Action::Dial
+            | Action::AdjustParameter
+            | Action::SetParameter
+            | Action::CycleParameter => None,
+            Action::SelectHeadphones => Some(Output::Headphones),
+            Action::SelectSpeakers => Some(Output::Speakers),
+        }
+    }
+
+    /// Whether the key has a state for each output.
+    pub fn shows_output(self) -> bool {
+        self == Action::SelectOutput
+    }
+}
+
+/// A key showing one of the plugin's actions.
+#[derive(Clone, Debug)]
+pub struct Key {
+    pub action: Action,
+    /// The deck the key is on.
+    pub device: String,
+    /// The settings stored with the key.
+    pub settings: serde_json::Value,
+    /// The title the plugin last set, or `None` for the one chosen in the
+    /// Stream Deck software.
+    pub title: Option<String>,
+    /// Whether the user has typed a title of their own, which live titles
+    /// must not replace.
+    pub custom_title: bool,
+    /// Titles the plugin has set that the Stream Deck software may still
+    /// report back, oldest first.
+    pub sent_titles: VecDeque<String>,
+    /// Whether the user wants the title shown at all.
+    pub show_title: bool,
+    /// What the plugin last showed on the touch strip, for dials.
+    pub feedback: Option<serde_json::Value>,
+    /// What the image the plugin last set shows, or `None` for the one
+    /// chosen in the Stream Deck software.
+    pub image: Option<Look>,
+}
+
+impl Key {
+    pub fn new(action: Action, device: &str, settings: &serde_json::Value) -> Self {
+        Key {
+            action,
+            device: device.to_owned(),
+            settings: settings.clone(),
+            title: None,
+            custom_title: false,
+            sent_titles: VecDeque::new(),
+            show_title: true,
+            feedback: None,
+            image: None,
+        }
+    }
+
+    /// Remember a title the plugin has set.
+    pub fn title_sent(&mut self, title: &str) {
+        self.sent_titles.push_back(title.to_owned());
+        if self.sent_titles.len() > MAX_SENT_TITLES {
+            self.sent_titles.pop_front();
+        }
+    }
+
+    /// Whether a title reported by the Stream Deck software is one the plugin
+    /// set, even if it has set others since.
+    ///
+    /// Titles are reported in the order they were set, so any set before this
+    /// one will not be reported again and are forgotten.
+    pub fn is_sent_title(&mut self, title: &str) -> bool {
+        match self.sent_titles.iter().position(|sent| sent == title) {
+            Some(i) => {
+                self.sent_titles.drain(..i);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// How many titles set by the plugin are remembered while waiting for the
+/// Stream Deck software to report them back.
+const MAX_SENT_TITLES: usize = 8;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WatcherStatus {
+    Starting,
+    Running,
+    Failed,
+}
+
+/// The property inspector that most recently talked to the plugin.
+pub struct Inspector {
+    pub action: String,
+    pub context: String,
+}
+
+/// A key press waiting for the current switch to finish.
+#[derive(Debug)]
+pub struct Press {
+    pub output: Output,
+    pub context: String,
+    /// Whether to apply the volume saved with the profile.
+    pub apply_volume: bool,
+    /// Whether to apply the profile again if the output is already selected.
+    pub reapply: bool,
+}
+
+/// Switches requested with the keys. Only one runs at a time.
+#[derive(Debug, Default)]
+pub struct Switching {
+    /// The output being switched to.
+    pub running: Option<Output>,
+    /// The latest press made while a switch was running.
+    pub pending: Option<Press>,
+    /// Changes asked for by keys and dials while a switch was running, with
+    /// the key or dial that asked. They are made once it finishes, so that
+    /// they do not race with it.
+    pub deferred: Vec<(String, ChangeEvent)>,
+    /// When the last press of each key that was not ignored happened.
+    pub last_press: BTreeMap<String, Instant>,
+}
+
+impl Switching {
+    /// The output the keys should show once everything requested is done.
+    pub fn target(&self) -> Option<Output> {
+        self.pending
+            .as_ref()
+            .map(|press| press.output)
+            .or(self.running)
+    }
+}
+
+/// Something for the task that owns the plugin state to handle.
+#[derive(Debug)]
+pub enum Command {
+    /// The plugin has (re)registered with the Stream Deck software.
+    Connected,
+    /// A message from the Stream Deck software, for a key or from the property inspector.
+    Message(InMessage),
+    /// A message from the Stream Deck software about a dial.
+    Dial(DialMessage),
+    /// Something happened to the sound card.
+    Watch(WatchEvent),
+    /// A switch started by a key press has finished.
+    Switched {
+        output: Output,
+        /// The profile as it was applied.
+        profile: Profile,
+        /// The key that started the switch, if any.
+        context: Option<String>,
+        result: Result<(), Error>,
+    },
+    /// Windows has a new default playback device.
+    DefaultEndpoint(Endpoint),
+    /// The sound card has been found again after going missing.
+    CardReturned(Mirror),
+    /// Reply with the settings to be saved, or nothing if they must not be saved yet.
+    Snapshot(oneshot::Sender<Option<serde_json::Value>>),
+    /// Stop handling commands, so that nothing more is sent to the Stream Deck
+    /// software, and reply once stopped.
+    Stop(oneshot::Sender<()>),
+}
+
+/// The plugin state, owned by a single task and changed only in response to a [`Command`].
 pub struct RawState {
     pub output: Option<Output>,
-    pub contexts: BTreeSet<String>,
-    pub out: mpsc::Sender<MessageOut<SerdeCardSettings, Empty, ToInspector>>,
+    /// The visible keys.
+    pub contexts: BTreeMap<String, Key>,
+    /// Keys on decks that have been unplugged, in case they come back.
+    pub detached: BTreeMap<String, Key>,
+    pub watcher: WatcherStatus,
+    pub inspector: Option<Inspector>,
+    pub out: mpsc::UnboundedSender<OutMessage>,
+    pub saver: Saver,
+    pub device: Device,
+    /// For reporting back once work done in the background has finished.
+    pub commands: mpsc::UnboundedSender<Command>,
+    pub switch: Switching,
+    /// The last thing that went wrong, until something works again.
+    pub last_error: Option<PluginError>,
+    /// Whether the sound card has gone away and is being waited for.
+    pub card_lost: bool,
+    pub reattach: Reattach,
+    /// The default playback device, if known.
+    pub default_endpoint: Option<Endpoint>,
+    /// Whether any keys are showing, which is the only time the default
+    /// playback device needs checking.
+    pub keys_shown: watch::Sender<bool>,
+    /// Whether the default playback device is some other device, so the
+    /// sound card must be left alone.
+    pub paused: bool,
+    /// Whether the sound card is muted, if known.
+    pub muted: Option<bool>,
+    pub images: Images,
+    /// How many pixels across key images are on each deck.
+    pub image_sizes: BTreeMap<String, u32>,
+    /// The pixel ratio of the display, for decks that are not known yet.
+    pub pixel_ratio: u8,
+    /// The last known state of the sound card, if it can be trusted.
+    pub mirror: Option<Mirror>,
+    /// What the sound card accepts for each parameter, once it has been read.
+    pub catalog: Option<Catalog>,
     pub settings: CardSettings,
+    /// Whether `settings` includes the settings stored by the Stream Deck software.
+    pub settings_loaded: bool,
+    /// Changes seen before the stored settings arrived, and the output that was active for each.
+    pub early_events: Vec<(Output, ChangeEvent)>,
+    /// Stored settings that could not be loaded and must not be overwritten.
+    pub quarantine: Option<Quarantined>,
+}
+
+pub struct Quarantined {
+    pub settings: serde_json::Value,
+    pub error: String,
 }
 
-pub type State = Arc<Mutex<RawState>>;
+impl RawState {
+    /// How sound card calls should be retried.
+    pub fn retry(&self) -> Retry {
+        Retry::from(&self.settings.options)
+    }
+
+    /// Start or stop checking the default playback device as keys come and go.
+    pub fn keys_changed(&self) {
+        // nobody is listening once the plugin is stopping
+        let _ = self.keys_shown.broadcast(!self.contexts.is_empty());
+    }
+
+    /// How many pixels across key images are on `device`.
+    pub fn image_size(&self, device: &str) -> u32 {
+        match self.image_sizes.get(device) {
+            Some(&size) => size,
+            None => image::key_size(5, 3, self.pixel_ratio),
+        }
+    }
+
+    /// The sound card that calls go to, or `None` for the default playback
+    /// device until the card has been identified.
+    pub fn card(&self) -> Option<String> {
+        self.settings.card.clone()
+    }
+
+    /// Save once the settings stop changing.
+    pub fn save_later(&self) {
+        self.saver
+            .later(Duration::from_millis(self.settings.options.save_delay_ms));
+    }
+
+    /// Queue a message for the Stream Deck software.
+    ///
+    /// This never waits, so a slow connection cannot hold up the state. Messages
+    /// can only fail to be queued once the connection has given up, when there
+    /// is nobody left to tell.
+    pub fn send(&self, message: OutMessage) {
+        let _ = self.out.unbounded_send(message);
+    }
+}
 
 #[derive(Debug, Default)]
 pub struct CardSettings {
     pub selected_parameters: IndexMap<String, IndexSet<String>>,
     pub profiles: Profiles,
+    pub options: Options,
+    /// The endpoint ID of the sound card, once one has been found.
+    pub card: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn late_title_echoes_are_not_custom() {
+        let mut key = Key::new(Action::SelectOutput, "DECK", &serde_json::Value::Null);
+        key.title_sent("HP 40%");
+        key.title_sent("HP 41%");
+        key.title_sent("HP 42%");
+        assert!(key.is_sent_title("HP 41%"));
+        // older titles will not be reported again
+        assert!(!key.is_sent_title("HP 40%"));
+        assert!(key.is_sent_title("HP 42%"));
+        // the current title can be reported any number of times
+        assert!(key.is_sent_title("HP 42%"));
+        assert!(!key.is_sent_title("Games"));
+    }
 }
@@ -0,0 +1,328 @@
+//! A stand-in for the Stream Deck software.
+//!
+//! The simulator listens for the plugin's websocket connection, performs the
+//! registration handshake, replays a script of events, and records every
+//! message the plugin sends back so that whole scenarios can be checked
+//! without the real Stream Deck application.
+//!
+//! ```no_run
+//! # async fn scenario() -> Result<(), simulator::Error> {
+//! use simulator::{Event, KeyPayload, Script, Simulator, DEVICE};
+//! use std::time::Duration;
+//!
+//! let simulator = Simulator::bind().await?;
+//! let mut plugin = simulator.spawn_plugin("target/i686-pc-windows-msvc/debug/plugin.exe")?;
+//! let script = Script::new()
+//!     .wait_for("getGlobalSettings", Duration::from_secs(5))
+//!     .send(&Event::<(), (), ()>::KeyUp {
+//!         action: "io.github.mdonoughe.sbzdeck.selectoutput".to_string(),
+//!         context: "KEY".to_string(),
+//!         device: DEVICE.to_string(),
+//!         payload: KeyPayload {
+//!             settings: (),
+//!             coordinates: None,
+//!             state: Some(0),
+//!             user_desired_state: None,
+//!             is_in_multi_action: false,
+//!         },
+//!     })
+//!     .wait_for("showOk", Duration::from_secs(5));
+//! let transcript = simulator.run(script).await?;
+//! assert_eq!(transcript.events("showOk").count(), 1);
+//! plugin.kill()?;
+//! # Ok(())
+//! # }
+//! ```
+
+mod message;
+
+pub use crate::message::*;
+
+use futures::prelude::*;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::ffi::OsStr;
+use std::process::{Child, Command};
+use std::time::Duration;
+use std::{fmt, io};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{self, Instant};
+use tokio_tungstenite::tungstenite;
+use tokio_tungstenite::WebSocketStream;
+
+/// The uuid the simulator assigns to the plugin.
+pub const PLUGIN_UUID: &str = "SIMULATED-PLUGIN";
+/// The event the plugin must use to register itself.
+pub const REGISTER_EVENT: &str = "registerPlugin";
+/// The id of the simulated Stream Deck device.
+pub const DEVICE: &str = "SIMULATED-DEVICE";
+
+/// An error encountered while running a scenario.
+#[derive(Debug)]
+pub enum Error {
+    /// The plugin could not be accepted or launched.
+    Io(io::Error),
+    /// The websocket connection failed.
+    WebSocket(tungstenite::Error),
+    /// The plugin sent something other than the expected registration message.
+    Registration(String),
+    /// The plugin did not send the expected event in time.
+    Timeout {
+        /// The name of the event that was expected.
+        event: String,
+        /// Everything the plugin sent before giving up.
+        transcript: Transcript,
+    },
+    /// The plugin closed the connection before the script finished.
+    Disconnected(Transcript),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "I/O error: {}", error),
+            Error::WebSocket(error) => write!(f, "websocket error: {}", error),
+            Error::Registration(message) => write!(f, "unexpected registration: {}", message),
+            Error::Timeout { event, .. } => write!(f, "timed out waiting for {}", event),
+            Error::Disconnected(_) => f.write_str("the plugin disconnected"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<tungstenite::Error> for Error {
+    fn from(error: tungstenite::Error) -> Self {
+        Error::WebSocket(error)
+    }
+}
+
+enum Step {
+    Send(Value),
+    Wait(Duration),
+    WaitFor { event: String, timeout: Duration },
+}
+
+/// A sequence of events to replay to the plugin.
+#[derive(Default)]
+pub struct Script {
+    steps: Vec<Step>,
+}
+
+impl Script {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send an event to the plugin.
+    pub fn send<G, S, M>(mut self, event: &Event<G, S, M>) -> Self
+    where
+        G: Serialize,
+        S: Serialize,
+        M: Serialize,
+    {
+        let event = serde_json::to_value(event).expect("event could not be serialized");
+        self.steps.push(Step::Send(event));
+        self
+    }
+
+    /// Send a raw JSON message to the plugin.
+    pub fn send_raw(mut self, message: Value) -> Self {
+        self.steps.push(Step::Send(message));
+        self
+    }
+
+    /// Record whatever the plugin sends for a fixed amount of time.
+    pub fn wait(mut self, duration: Duration) -> Self {
+        self.steps.push(Step::Wait(duration));
+        self
+    }
+
+    /// Record whatever the plugin sends until it sends the named event.
+    ///
+    /// The scenario fails if the event does not arrive within `timeout`.
+    pub fn wait_for(mut self, event: &str, timeout: Duration) -> Self {
+        self.steps.push(Step::WaitFor {
+            event: event.to_string(),
+            timeout,
+        });
+        self
+    }
+}
+
+/// Everything the plugin sent during a scenario, in order.
+#[derive(Debug, Default)]
+pub struct Transcript {
+    pub messages: Vec<Value>,
+}
+
+impl Transcript {
+    /// The messages with the given event name.
+    pub fn events<'a>(&'a self, event: &'a str) -> impl Iterator<Item = &'a Value> + 'a {
+        self.messages
+            .iter()
+            .filter(move |message| message["event"] == event)
+    }
+}
+
+enum Received {
+    Matched,
+    TimedOut,
+    Closed,
+}
+
+/// A simulated Stream Deck application waiting for a plugin to connect.
+pub struct Simulator {
+    listener: TcpListener,
+    port: u16,
+    info: Value,
+}
+
+impl Simulator {
+    /// Listen on an unused local port.
+    pub async fn bind() -> io::Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+        let port = listener.local_addr()?.port();
+        Ok(Self {
+            listener,
+            port,
+            info: json!({
+                "application": {
+                    "language": "en",
+                    "platform": "windows",
+                    "version": "4.6.4"
+                },
+                "devicePixelRatio": 1,
+                "devices": [
+                    {
+                        "id": DEVICE,
+                        "size": { "columns": 5, "rows": 3 },
+                        "type": 0
+                    }
+                ]
+            }),
+        })
+    }
+
+    /// Replace the `info` parameter passed to the plugin.
+    pub fn with_info(mut self, info: Value) -> Self {
+        self.info = info;
+        self
+    }
+
+    /// The port the simulator is listening on.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// The command line arguments the Stream Deck software would pass to the plugin.
+    pub fn args(&self) -> Vec<String> {
+        vec![
+            "-port".to_string(),
+            self.port.to_string(),
+            "-pluginUUID".to_string(),
+            PLUGIN_UUID.to_string(),
+            "-registerEvent".to_string(),
+            REGISTER_EVENT.to_string(),
+            "-info".to_string(),
+            self.info.to_string(),
+        ]
+    }
+
+    /// Launch a plugin executable pointed at this simulator.
+    pub fn spawn_plugin<P: AsRef<OsStr>>(&self, program: P) -> io::Result<Child> {
+        Command::new(program).args(self.args()).spawn()
+    }
+
+    /// Wait for the plugin to connect and register, then play the script.
+    pub async fn run(mut self, script: Script) -> Result<Transcript, Error> {
+        let (stream, _) = self.listener.accept().await?;
+        let mut socket = tokio_tungstenite::accept_async(stream).await?;
+        register(&mut socket).await?;
+
+        let mut transcript = Transcript::default();
+        for step in script.steps {
+            match step {
+                Step::Send(message) => {
+                    socket
+                        .send(tungstenite::Message::Text(message.to_string()))
+                        .await?
+                }
+                Step::Wait(duration) => {
+                    let deadline = Instant::now() + duration;
+                    if let Received::Closed =
+                        record_until(&mut socket, &mut transcript, deadline, None).await?
+                    {
+                        return Err(Error::Disconnected(transcript));
+                    }
+                }
+                Step::WaitFor { event, timeout } => {
+                    let deadline = Instant::now() + timeout;
+                    match record_until(&mut socket, &mut transcript, deadline, Some(&event)).await?
+                    {
+                        Received::Matched => {}
+                        Received::TimedOut => return Err(Error::Timeout { event, transcript }),
+                        Received::Closed => return Err(Error::Disconnected(transcript)),
+                    }
+                }
+            }
+        }
+
+        let _ = socket.send(tungstenite::Message::Close(None)).await;
+        Ok(transcript)
+    }
+}
+
+async fn register(socket: &mut WebSocketStream<TcpStream>) -> Result<(), Error> {
+    let text = loop {
+        match socket.next().await {
+            Some(Ok(tungstenite::Message::Text(text))) => break text,
+            Some(Ok(tungstenite::Message::Close(_))) | None => {
+                return Err(Error::Registration("connection closed".to_string()))
+            }
+            Some(Ok(_)) => {}
+            Some(Err(error)) => return Err(error.into()),
+        }
+    };
+    let registration: Value =
+        serde_json::from_str(&text).map_err(|_| Error::Registration(text.to_string()))?;
+    if registration["event"] == REGISTER_EVENT && registration["uuid"] == PLUGIN_UUID {
+        Ok(())
+    } else {
+        Err(Error::Registration(text))
+    }
+}
+
+async fn record_until(
+    socket: &mut WebSocketStream<TcpStream>,
+    transcript: &mut Transcript,
+    deadline: Instant,
+    event: Option<&str>,
+) -> Result<Received, Error> {
+    loop {
+        let message = match time::timeout_at(deadline, socket.next()).await {
+            Err(_) => return Ok(Received::TimedOut),
+            Ok(None) | Ok(Some(Ok(tungstenite::Message::Close(_)))) => return Ok(Received::Closed),
+            Ok(Some(Ok(tungstenite::Message::Text(text)))) => text,
+            Ok(Some(Ok(_))) => continue,
+            Ok(Some(Err(error))) => return Err(error.into()),
+        };
+        let message: Value = match serde_json::from_str(&message) {
+            Ok(message) => message,
+            Err(_) => Value::String(message),
+        };
+        let matched = event
+            .map(|event| message["event"] == event)
+            .unwrap_or(false);
+        transcript.messages.push(message);
+        if matched {
+            return Ok(Received::Matched);
+        }
+    }
+}
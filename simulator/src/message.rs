@@ -0,0 +1,182 @@
+use serde_derive::Serialize;
+
+/// A message sent to the plugin by the simulated Stream Deck software.
+///
+/// - `G` represents the global settings that are persisted within the Stream Deck software.
+/// - `S` represents the action settings that are persisted within the Stream Deck software.
+/// - `M` represents the messages that are sent from the property inspector.
+///
+/// [Official Documentation](https://developer.elgato.com/documentation/stream-deck/sdk/events-received/)
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "camelCase")]
+pub enum Event<G, S, M> {
+    /// A key has been pressed.
+    ///
+    /// [Official Documentation](https://developer.elgato.com/documentation/stream-deck/sdk/events-received/#keydown)
+    #[serde(rename_all = "camelCase")]
+    KeyDown {
+        /// The uuid of the action.
+        action: String,
+        /// The instance of the action (key or part of a multiaction).
+        context: String,
+        /// The device where the key was pressed.
+        device: String,
+        /// Additional information about the key press.
+        payload: KeyPayload<S>,
+    },
+    /// A key has been released.
+    ///
+    /// [Official Documentation](https://developer.elgato.com/documentation/stream-deck/sdk/events-received/#keyup)
+    #[serde(rename_all = "camelCase")]
+    KeyUp {
+        /// The uuid of the action.
+        action: String,
+        /// The instance of the action (key or part of a multiaction).
+        context: String,
+        /// The device where the key was pressed.
+        device: String,
+        /// Additional information about the key press.
+        payload: KeyPayload<S>,
+    },
+    /// An instance of the action has been added to the display.
+    ///
+    /// [Official Documentation](https://developer.elgato.com/documentation/stream-deck/sdk/events-received/#willappear)
+    #[serde(rename_all = "camelCase")]
+    WillAppear {
+        /// The uuid of the action.
+        action: String,
+        /// The instance of the action (key or part of a multiaction).
+        context: String,
+        /// The device where the action will appear.
+        device: String,
+        /// Additional information about the action's appearance.
+        payload: VisibilityPayload<S>,
+    },
+    /// An instance of the action has been removed from the display.
+    ///
+    /// [Official Documentation](https://developer.elgato.com/documentation/stream-deck/sdk/events-received/#willdisappear)
+    #[serde(rename_all = "camelCase")]
+    WillDisappear {
+        /// The uuid of the action.
+        action: String,
+        /// The instance of the action (key or part of a multiaction).
+        context: String,
+        /// The device where the action was visible.
+        device: String,
+        /// Additional information about the action's appearance.
+        payload: VisibilityPayload<S>,
+    },
+    /// The property inspector for an action has become visible.
+    ///
+    /// [Official Documentation](https://developer.elgato.com/documentation/stream-deck/sdk/events-received/#propertyinspectordidappear)
+    #[serde(rename_all = "camelCase")]
+    PropertyInspectorDidAppear {
+        /// The uuid of the action.
+        action: String,
+        /// The instance of the action (key or part of a multiaction).
+        context: String,
+        /// The device where the action exists.
+        device: String,
+    },
+    /// The property inspector for an action is no longer visible.
+    ///
+    /// [Official Documentation](https://developer.elgato.com/documentation/stream-deck/sdk/events-received/#propertyinspectordiddisappear)
+    #[serde(rename_all = "camelCase")]
+    PropertyInspectorDidDisappear {
+        /// The uuid of the action.
+        action: String,
+        /// The instance of the action (key or part of a multiaction).
+        context: String,
+        /// The device where the action exists.
+        device: String,
+    },
+    /// The property inspector has sent data.
+    ///
+    /// [Official Documentation](https://developer.elgato.com/documentation/stream-deck/sdk/events-received/#sendtoplugin)
+    #[serde(rename_all = "camelCase")]
+    SendToPlugin {
+        /// The uuid of the action.
+        action: String,
+        /// The instance of the action (key or part of a multiaction).
+        context: String,
+        /// Information sent from the property inspector.
+        payload: M,
+    },
+    /// The application has sent settings for an action.
+    ///
+    /// [Official Documentation](https://developer.elgato.com/documentation/stream-deck/sdk/events-received/#didreceivesettings)
+    #[serde(rename_all = "camelCase")]
+    DidReceiveSettings {
+        /// The uuid of the action.
+        action: String,
+        /// The instance of the action (key or part of a multiaction).
+        context: String,
+        /// The device where the action exists.
+        device: String,
+        /// The current settings for the action.
+        payload: KeyPayload<S>,
+    },
+    /// The application has sent settings for the plugin.
+    ///
+    /// [Official Documentation](https://developer.elgato.com/documentation/stream-deck/sdk/events-received/#didreceiveglobalsettings)
+    #[serde(rename_all = "camelCase")]
+    DidReceiveGlobalSettings {
+        /// The current settings for the plugin.
+        payload: GlobalSettingsPayload<G>,
+    },
+}
+
+/// Additional information about a key.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyPayload<S> {
+    /// The stored settings for the action instance.
+    pub settings: S,
+    /// The location of the key, or None if this action instance is part of a multi action.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coordinates: Option<Coordinates>,
+    /// The current state of the action instance.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<u8>,
+    /// The desired state of the action instance (if this instance is part of a multi action).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_desired_state: Option<u8>,
+    /// Whether the action instance is part of a multi action.
+    pub is_in_multi_action: bool,
+}
+
+/// Additional information about a key's appearance.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VisibilityPayload<S> {
+    /// The stored settings for the action instance.
+    pub settings: S,
+    /// The location of the key, or None if this action instance is part of a multi action.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coordinates: Option<Coordinates>,
+    /// The current state of the action instance.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<u8>,
+    /// Whether the action instance is part of a multi action.
+    pub is_in_multi_action: bool,
+}
+
+/// The new global settings.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GlobalSettingsPayload<G> {
+    /// The stored settings for the plugin.
+    pub settings: G,
+}
+
+/// The location of a key on a device.
+///
+/// Locations are specified using zero-indexed values starting from the top left corner of the device.
+#[derive(Clone, Copy, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Coordinates {
+    /// The x coordinate of the key.
+    pub column: u8,
+    /// The y-coordinate of the key.
+    pub row: u8,
+}
@@ -0,0 +1,71 @@
+use futures::prelude::*;
+use serde_json::{json, Value};
+use simulator::{Script, Simulator, PLUGIN_UUID, REGISTER_EVENT};
+use std::time::Duration;
+use tokio_tungstenite::tungstenite::Message;
+
+/// Connect the way the plugin does, register, and send `messages`.
+async fn fake_plugin(port: u16, messages: Vec<Value>) {
+    let url = format!("ws://127.0.0.1:{}", port);
+    let (mut socket, _) = tokio_tungstenite::connect_async(url.as_str())
+        .await
+        .expect("failed to connect to the simulator");
+    let registration = json!({ "event": REGISTER_EVENT, "uuid": PLUGIN_UUID });
+    socket
+        .send(Message::Text(registration.to_string()))
+        .await
+        .expect("failed to register");
+    for message in messages {
+        socket
+            .send(Message::Text(message.to_string()))
+            .await
+            .expect("failed to send");
+    }
+    // wait for the simulator to close the connection
+    while let Some(Ok(_)) = socket.next().await {}
+}
+
+#[tokio::test]
+async fn records_messages_after_registration() {
+    let simulator = Simulator::bind().await.expect("failed to bind");
+    let plugin = tokio::spawn(fake_plugin(
+        simulator.port(),
+        vec![
+            json!({ "event": "getGlobalSettings", "context": PLUGIN_UUID }),
+            json!({ "event": "showOk", "context": "KEY" }),
+        ],
+    ));
+
+    let script = Script::new()
+        .wait_for("getGlobalSettings", Duration::from_secs(5))
+        .wait_for("showOk", Duration::from_secs(5));
+    let transcript = simulator.run(script).await.expect("scenario failed");
+    plugin.await.expect("fake plugin panicked");
+
+    // the registration itself is not part of the transcript
+    assert_eq!(transcript.messages.len(), 2);
+    assert_eq!(transcript.messages[0]["event"], "getGlobalSettings");
+    assert_eq!(transcript.messages[0]["context"], PLUGIN_UUID);
+    assert_eq!(transcript.events("showOk").count(), 1);
+    assert_eq!(transcript.events(REGISTER_EVENT).count(), 0);
+}
+
+#[tokio::test]
+async fn rejects_the_wrong_registration() {
+    let simulator = Simulator::bind().await.expect("failed to bind");
+    let port = simulator.port();
+    let plugin = tokio::spawn(async move {
+        let url = format!("ws://127.0.0.1:{}", port);
+        let (mut socket, _) = tokio_tungstenite::connect_async(url.as_str())
+            .await
+            .expect("failed to connect to the simulator");
+        let registration = json!({ "event": REGISTER_EVENT, "uuid": "SOMEONE-ELSE" });
+        let _ = socket.send(Message::Text(registration.to_string())).await;
+    });
+
+    match simulator.run(Script::new()).await {
+        Err(simulator::Error::Registration(_)) => {}
+        other => panic!("expected a registration error, got {:?}", other.map(|_| ())),
+    }
+    plugin.await.expect("fake plugin panicked");
+}